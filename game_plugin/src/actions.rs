@@ -1,7 +1,7 @@
 #[cfg(debug_assertions)]
 use crate::character_status::Skill;
 #[cfg(debug_assertions)]
-use crate::effects::{skill_to_effect, EffectEvent};
+use crate::effects::{skill_to_effect, EffectEvent, Hit};
 #[cfg(debug_assertions)]
 use crate::enemies::EnemyData;
 #[cfg(debug_assertions)]
@@ -173,8 +173,8 @@ fn explore_debug_input(
     // デバッグ機能
     if keyboard_input.just_pressed(KeyCode::B) {
         if let Some((_map_camera, _transform, position)) = player_camera_query.iter_mut().next() {
-            let enemy = enemy_data.field_to_enemy(&map.position_to_field(&position));
-            events.send(GameEvent::EnemyEncountered(enemy.clone()));
+            let group = enemy_data.field_to_group(&map.encounter_field(&position));
+            events.send(GameEvent::EnemyEncountered(group));
         }
     }
     if keyboard_input.just_pressed(KeyCode::T) {
@@ -187,6 +187,8 @@ fn set_battle_actions(mut actions: ResMut<PlayerActions>, keyboard_input: Res<In
         || Action::Up.just_pressed(&keyboard_input)
         || Action::Down.just_released(&keyboard_input)
         || Action::Down.just_pressed(&keyboard_input)
+        || Action::Left.just_pressed(&keyboard_input)
+        || Action::Right.just_pressed(&keyboard_input)
         || Action::Return.just_released(&keyboard_input)
         || Action::Return.just_pressed(&keyboard_input)
     {
@@ -205,6 +207,11 @@ fn set_battle_actions(mut actions: ResMut<PlayerActions>, keyboard_input: Res<In
             player_command = Option::from(Action::Up);
         } else if Action::Down.just_pressed(&keyboard_input) {
             player_command = Option::from(Action::Down);
+        } else if Action::Left.just_pressed(&keyboard_input) {
+            // 攻撃対象の選択
+            player_command = Option::from(Action::Left);
+        } else if Action::Right.just_pressed(&keyboard_input) {
+            player_command = Option::from(Action::Right);
         } else {
             if let Some(action) = player_command {
                 if matches!(action, Action::Up | Action::Down) {
@@ -252,6 +259,8 @@ fn battle_debug_input(
             kind: skill_to_effect(Skill::Wind),
             damage_or_heal: 10,
             is_player_attack: true,
+            target: Some(0),
+            hit: Hit::Normal,
         });
         keyboard_input.reset(KeyCode::E);
     }
@@ -264,6 +273,15 @@ fn battle_debug_input(
 }
 
 fn set_event_actions(mut actions: ResMut<PlayerActions>, keyboard_input: Res<Input<KeyCode>>) {
+    // 町では 'S' でセーブするので、メニューは矢印キーだけで選ぶ
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        actions.action = Option::from(Action::Up);
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        actions.action = Option::from(Action::Down);
+        return;
+    }
     if Action::Return.just_released(&keyboard_input) || Action::Return.pressed(&keyboard_input) {
         let mut player_command = None;
         if Action::Return.just_released(&keyboard_input) {
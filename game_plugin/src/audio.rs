@@ -48,6 +48,7 @@ pub enum AudioKind {
     BGMWin,
     BGMLose,
     SEAttack,
+    SECritical,
     SEHeal,
     SETown,
 }
@@ -79,7 +80,7 @@ impl AudioState {
         kind: &AudioKind,
     ) -> Option<&mut (AudioChannel, ChannelAudioState)> {
         match kind {
-            AudioKind::SEAttack | AudioKind::SEHeal | AudioKind::SETown => {
+            AudioKind::SEAttack | AudioKind::SECritical | AudioKind::SEHeal | AudioKind::SETown => {
                 self.channels.get_mut("se")
             }
             _ => self.channels.get_mut("bgm"),
@@ -147,6 +148,10 @@ fn setup_audio(audio_assets: Res<AudioAssets>, mut audio_state: ResMut<AudioStat
         AudioKind::SEAttack,
         audio_assets.get_handle_for_audio(AudioKind::SEAttack),
     );
+    audio_state.sound_handles.insert(
+        AudioKind::SECritical,
+        audio_assets.get_handle_for_audio(AudioKind::SECritical),
+    );
     audio_state.sound_handles.insert(
         AudioKind::SEHeal,
         audio_assets.get_handle_for_audio(AudioKind::SEHeal),
@@ -177,7 +182,10 @@ fn control_audio(
                 let audio_source = audio_state.sound_handles[kind].clone();
                 let (channel, channel_audio_state) = audio_state.get_channel(kind).unwrap();
                 match kind {
-                    AudioKind::SEAttack | AudioKind::SEHeal | AudioKind::SETown => {
+                    AudioKind::SEAttack
+                    | AudioKind::SECritical
+                    | AudioKind::SEHeal
+                    | AudioKind::SETown => {
                         channel_audio_state.paused = false;
                         channel_audio_state.stopped = false;
                         audio.play_in_channel(audio_source, channel);
@@ -1,7 +1,8 @@
 use crate::actions::{Action, PlayerActions};
-use crate::character_status::CharacterStatus;
-use crate::enemies::Battle;
+use crate::character_status::{CharacterStatus, Skill, SkillTarget};
+use crate::enemies::{Battle, EnemySlot};
 use crate::inventory::Inventory;
+use crate::party::PartyMember;
 use crate::player::{Player, PlayerBattleState};
 use crate::AppState;
 use bevy::prelude::*;
@@ -21,25 +22,127 @@ impl Plugin for BattleActionsPlugin {
 
 fn exec_actions(
     actions: Res<PlayerActions>,
-    mut player_query: Query<(&mut CharacterStatus, &mut Inventory, &mut Player, Entity)>,
+    mut battle: ResMut<Battle>,
+    mut player_query: Query<&mut Player>,
+    mut party_query: Query<(&mut Inventory, &CharacterStatus, &PartyMember)>,
+    enemy_query: Query<(&CharacterStatus, &EnemySlot)>,
 ) {
     if matches!(actions.action, None) {
         return;
     }
 
-    if let Some((mut _character_status, mut inventory, mut player, _entity)) =
-        player_query.iter_mut().next()
+    // ボスの台詞は決定キーで読み進める
+    if !battle.dialogue.is_empty() {
+        if matches!(actions.action, Some(Action::Return)) {
+            battle.dialogue.pop_front();
+            if battle.dialogue.is_empty() {
+                for mut player in player_query.iter_mut() {
+                    player.battle_state = PlayerBattleState::NextTurn;
+                }
+            }
+        }
+        return;
+    }
+
+    // 生きている敵の位置 (左から順)
+    let mut enemies: Vec<usize> = enemy_query
+        .iter()
+        .filter(|(status, _)| status.hp_current > 0)
+        .map(|(_, slot)| slot.0)
+        .collect();
+    enemies.sort_unstable();
+    if enemies.is_empty() {
+        return;
+    }
+    // 回復魔法は倒れた仲間にも使える
+    let mut allies: Vec<usize> = party_query
+        .iter_mut()
+        .map(|(_, _, member)| member.order)
+        .collect();
+    allies.sort_unstable();
+
+    // 道具は主人公の持ち物を皆で使う
+    let stash = party_query
+        .iter_mut()
+        .find(|(_, _, member)| member.order == 0)
+        .map_or(Vec::new(), |(inventory, _, _)| {
+            inventory.consumables.clone()
+        });
+
+    let actor = battle.actor;
+    let mut player = match player_query.iter_mut().next() {
+        Some(player) => player,
+        None => return,
+    };
+    let (mut inventory, status) = match party_query
+        .iter_mut()
+        .find(|(_, _, member)| member.order == actor)
     {
-        match actions.action {
-            Some(Action::Up) => inventory.decrement_index(),
-            Some(Action::Down) => inventory.increment_index(),
+        Some((inventory, status, _)) => (inventory, status),
+        None => return,
+    };
+    let skill_target = inventory.skill(&stash).target();
+
+    match player.battle_state {
+        PlayerBattleState::Select => match actions.action {
+            Some(Action::Up) => inventory.decrement_index(&stash),
+            Some(Action::Down) => inventory.increment_index(&stash),
             Some(Action::Return) => {
-                if matches!(player.battle_state, PlayerBattleState::Select) {
-                    // state を更新
+                // ボスからは逃げられない
+                if inventory.skill(&stash) == Skill::Run && !battle.can_run {
+                    return;
+                }
+                if !status.can_use(&inventory.skill(&stash)) {
+                    return;
+                }
+                if !enemies.contains(&battle.target) {
+                    battle.target = enemies[0];
+                }
+                if !allies.contains(&battle.ally_target) {
+                    battle.ally_target = actor;
+                }
+                let choices = match skill_target {
+                    SkillTarget::Enemy => enemies.len(),
+                    SkillTarget::Ally => allies.len(),
+                    SkillTarget::Myself | SkillTarget::AllEnemies => 1,
+                };
+                // state を更新
+                if choices > 1 {
+                    player.battle_state = PlayerBattleState::Target;
+                } else {
                     player.battle_state = PlayerBattleState::Attack;
                 }
             }
             _ => {}
-        }
+        },
+        PlayerBattleState::Target => match actions.action {
+            Some(Action::Left) | Some(Action::Right) => {
+                let forward = matches!(actions.action, Some(Action::Right));
+                if skill_target == SkillTarget::Ally {
+                    battle.ally_target = cycle(&allies, battle.ally_target, forward);
+                } else {
+                    battle.target = cycle(&enemies, battle.target, forward);
+                }
+            }
+            // 技の選択に戻る
+            Some(Action::Up) | Some(Action::Down) => {
+                player.battle_state = PlayerBattleState::Select;
+            }
+            Some(Action::Return) => {
+                player.battle_state = PlayerBattleState::Attack;
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+// The next or previous entry of `list` after `current`
+fn cycle(list: &[usize], current: usize, forward: bool) -> usize {
+    let index = list.iter().position(|&i| i == current).unwrap_or(0);
+    if forward {
+        list[(index + 1) % list.len()]
+    } else {
+        list[(index + list.len() - 1) % list.len()]
     }
 }
@@ -0,0 +1,149 @@
+use crate::audio::{AudioEvent, AudioKind};
+use crate::character_status::CharacterStatus;
+use crate::element::{Affinities, Element};
+use crate::enemies::Battle;
+use crate::enemy_ai::{EnemyAi, SkillChoice};
+use crate::AppState;
+use bevy::prelude::*;
+
+pub struct BossPlugin;
+
+// This plugin changes the phases of bosses as they lose HP
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(AppState::InGameBattle).with_system(advance_phases.system()),
+        );
+    }
+}
+
+// A stage of a boss fight. The first stage is the enemy's own data,
+// the later ones begin when its HP falls low enough.
+#[derive(Clone, Debug)]
+pub struct Phase {
+    // begins below this fraction of the max HP
+    pub below: f32,
+    pub skills: &'static [SkillChoice],
+    pub tint: Color,
+    pub affinities: &'static [(Element, f32)],
+    // music switched to, None keeps playing the current one
+    pub bgm: Option<AudioKind>,
+    // shown in the event window before the fight goes on
+    pub lines: &'static [&'static str],
+}
+
+// Later phases of an enemy and how many of them have begun
+#[derive(Clone, Debug, Default)]
+pub struct Phases {
+    pub phases: &'static [Phase],
+    pub passed: usize,
+}
+
+impl Phases {
+    pub fn new(phases: &'static [Phase]) -> Self {
+        Phases { phases, passed: 0 }
+    }
+    // The phase that begins now, one at a time
+    pub fn next(&mut self, status: &CharacterStatus) -> Option<&'static Phase> {
+        let phase = self.phases.get(self.passed)?;
+        if status.hp_current <= 0 || status.hp_current as f32 >= status.hp_max as f32 * phase.below
+        {
+            return None;
+        }
+        self.passed += 1;
+        Some(phase)
+    }
+}
+
+fn advance_phases(
+    mut battle: ResMut<Battle>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut audio_event_writer: EventWriter<AudioEvent>,
+    mut query: Query<(
+        &CharacterStatus,
+        &mut Phases,
+        &mut EnemyAi,
+        &mut Affinities,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    for (status, mut phases, mut ai, mut affinities, material) in query.iter_mut() {
+        while let Some(phase) = phases.next(status) {
+            // 覚えた行動 (一度きりの技など) はそのまま引き継ぐ
+            ai.choices = phase.skills.to_vec();
+            *affinities = Affinities(phase.affinities.to_vec());
+            if let Some(material) = materials.get_mut(material) {
+                material.color = phase.tint;
+            }
+            if let Some(bgm) = &phase.bgm {
+                audio_event_writer.send(AudioEvent::Stop(AudioKind::BGMBattle));
+                audio_event_writer.send(AudioEvent::Play(bgm.clone()));
+            }
+            battle
+                .dialogue
+                .extend(phase.lines.iter().map(|line| line.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHASES: &[Phase] = &[
+        Phase {
+            below: 0.5,
+            skills: &[],
+            tint: Color::WHITE,
+            affinities: &[],
+            bgm: None,
+            lines: &["Half"],
+        },
+        Phase {
+            below: 0.2,
+            skills: &[],
+            tint: Color::WHITE,
+            affinities: &[],
+            bgm: None,
+            lines: &["Last"],
+        },
+    ];
+
+    fn status(hp_current: i32) -> CharacterStatus {
+        CharacterStatus {
+            hp_current,
+            hp_max: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn nothing_happens_above_the_threshold() {
+        let mut phases = Phases::new(PHASES);
+        assert!(phases.next(&status(50)).is_none());
+        assert_eq!(phases.passed, 0);
+    }
+
+    #[test]
+    fn phases_begin_in_order() {
+        let mut phases = Phases::new(PHASES);
+        assert_eq!(phases.next(&status(49)).unwrap().lines, &["Half"]);
+        assert!(phases.next(&status(49)).is_none());
+        assert_eq!(phases.next(&status(10)).unwrap().lines, &["Last"]);
+        assert!(phases.next(&status(1)).is_none());
+    }
+
+    #[test]
+    fn a_big_hit_passes_every_phase() {
+        let mut phases = Phases::new(PHASES);
+        assert!(phases.next(&status(5)).is_some());
+        assert!(phases.next(&status(5)).is_some());
+        assert_eq!(phases.passed, 2);
+    }
+
+    #[test]
+    fn defeated_bosses_say_nothing() {
+        let mut phases = Phases::new(PHASES);
+        assert!(phases.next(&status(0)).is_none());
+    }
+}
@@ -1,8 +1,11 @@
+use crate::equipment::Equipment;
+use crate::growth::{Growth, MAX_EXP};
 use crate::inventory::{Inventory, Item};
 use core::fmt;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CharacterStatus {
     pub name: String,
     pub lv: i32,
@@ -13,6 +16,17 @@ pub struct CharacterStatus {
     pub mp_max: i32,
     pub attack: i32,
     pub defence: i32,
+    pub speed: i32,
+    // chance to hit in percent, lowered by the evasion of the target
+    pub accuracy: i32,
+    pub evasion: i32,
+    // chance of a critical hit in percent
+    pub critical: i32,
+    // halves damage until the end of the round
+    #[serde(skip)]
+    pub defending: bool,
+    // ailments and the turns they still last
+    pub ailments: Vec<(Ailment, i32)>,
 }
 
 impl Default for CharacterStatus {
@@ -27,6 +41,12 @@ impl Default for CharacterStatus {
             mp_max: 100,
             attack: 10,
             defence: 10,
+            speed: 10,
+            accuracy: 95,
+            evasion: 5,
+            critical: 5,
+            defending: false,
+            ailments: Vec::new(),
         }
     }
 }
@@ -35,10 +55,12 @@ impl Display for CharacterStatus {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         write!(
             fmt,
-            "Lv {0:>2} Exp {1:>3}\n\
+            "Lv {0:>2} Exp {1:>4}\n\
                      HP {2:>3} / {3:>3}\n\
                      MP {4:>3} / {5:>3}\n\
-                     AT {6:>3} DF {7:>3}\n",
+                     AT {6:>3} DF {7:>3}\n\
+                     SP {8:>3} CR {9:>3}\n\
+                     {10}",
             self.lv,
             self.exp,
             self.hp_current,
@@ -46,80 +68,315 @@ impl Display for CharacterStatus {
             self.mp_current,
             self.mp_max,
             self.attack,
-            self.defence
+            self.defence,
+            self.speed,
+            self.critical,
+            self.ailment_text()
         )
     }
 }
 
-pub const LEVEL_LIST: [i32; 32] = [
-    0, 10, 20, 40, 60, 80, 100, 125, 150, 175, 200, 225, 250, 275, 300, 325, 350, 375, 400, 425,
-    450, 500, 550, 600, 650, 700, 750, 800, 850, 900, 950, 999,
-];
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Ailment {
+    // loses HP every turn, and every step on the map
+    Poison,
+    // can't act, wakes up when hit
+    Sleep,
+    // can't act
+    Paralysis,
+    // can't cast spells
+    Silence,
+}
+
+impl Ailment {
+    // Short name for the status windows
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Ailment::Poison => "Psn",
+            Ailment::Sleep => "Slp",
+            Ailment::Paralysis => "Par",
+            Ailment::Silence => "Sil",
+        }
+    }
+}
+
+impl Display for Ailment {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, fmt)
+    }
+}
 
 impl CharacterStatus {
     pub fn enemy_text(&self) -> String {
         let ret = format!(
-            "{0} Lv {1:>2} HP {2:>3} / {3:>3} AT {4:>3} DF {5:>3}",
-            self.name, self.lv, self.hp_current, self.hp_max, self.attack, self.defence
+            "{0} Lv {1:>2} HP {2:>3} / {3:>3} AT {4:>3} DF {5:>3} SP {6:>3} {7}",
+            self.name,
+            self.lv,
+            self.hp_current,
+            self.hp_max,
+            self.attack,
+            self.defence,
+            self.speed,
+            self.ailment_text()
         );
         ret
     }
+    // Two lines per member in the party window
+    pub fn party_text(&self) -> String {
+        let name = if self.hp_current <= 0 {
+            format!("{} (down)", self.name)
+        } else {
+            format!("{} {}", self.name, self.ailment_text())
+        };
+        format!(
+            "{0} Lv {1:>2}\n  HP {2:>3}/{3:>3} MP {4:>3}/{5:>3}\n",
+            name, self.lv, self.hp_current, self.hp_max, self.mp_current, self.mp_max
+        )
+    }
+    // e.g. "Psn Sil"
+    pub fn ailment_text(&self) -> String {
+        self.ailments
+            .iter()
+            .map(|(ailment, _)| ailment.tag())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+    pub fn has(&self, ailment: Ailment) -> bool {
+        self.ailments.iter().any(|(a, _)| *a == ailment)
+    }
+    // Suffering it again only makes it last longer
+    pub fn inflict(&mut self, ailment: Ailment, turns: i32) {
+        if self.hp_current <= 0 {
+            return;
+        }
+        match self.ailments.iter_mut().find(|(a, _)| *a == ailment) {
+            Some((_, left)) => *left = (*left).max(turns),
+            None => self.ailments.push((ailment, turns)),
+        }
+    }
+    pub fn cure(&mut self, ailments: &[Ailment]) {
+        self.ailments.retain(|(a, _)| !ailments.contains(a));
+    }
+    // 眠り・麻痺なら行動できない
+    pub fn stopped_by(&self) -> Option<Ailment> {
+        [Ailment::Sleep, Ailment::Paralysis]
+            .iter()
+            .copied()
+            .find(|ailment| self.has(*ailment))
+    }
+    // 沈黙中は呪文を唱えられない
+    pub fn can_use(&self, skill: &Skill) -> bool {
+        !(skill.is_spell() && self.has(Ailment::Silence))
+    }
+    // Start of the character's turn in battle. Poison hurts, then every
+    // ailment lasts one turn less. Returns the poison damage.
+    pub fn tick_ailments(&mut self) -> i32 {
+        let damage = if self.has(Ailment::Poison) {
+            let damage = (self.hp_max / 10).max(1);
+            self.hp_current = (self.hp_current - damage).clamp(0, self.hp_max);
+            damage
+        } else {
+            0
+        };
+        self.count_down();
+        damage
+    }
+    // Poison on the map never takes the last HP
+    pub fn poison_step(&mut self) {
+        if self.hp_current <= 0 || !self.has(Ailment::Poison) {
+            return;
+        }
+        let damage = (self.hp_max / 20).max(1);
+        self.hp_current = (self.hp_current - damage).max(1);
+        self.count_down();
+    }
+    fn count_down(&mut self) {
+        for (_, left) in self.ailments.iter_mut() {
+            *left -= 1;
+        }
+        self.ailments.retain(|(_, left)| *left > 0);
+    }
+    // Defending only lasts for the round it was chosen in
+    pub fn start_round(&mut self) {
+        self.defending = false;
+    }
+    // Only poison lasts after the battle
+    pub fn end_battle(&mut self) {
+        self.defending = false;
+        self.ailments.retain(|(a, _)| *a == Ailment::Poison);
+    }
+    // Herbs, magic water and antidotes.
+    // Returns the HP and MP actually restored and whether an ailment was cured.
+    pub fn use_item(&mut self, item: &Item) -> (i32, i32, bool) {
+        let (hp, mp) = item.restores();
+        let before = (self.hp_current, self.mp_current, self.ailments.len());
+        self.hp_current = (self.hp_current + hp).clamp(0, self.hp_max);
+        self.mp_current = (self.mp_current + mp).clamp(0, self.mp_max);
+        self.cure(item.cures());
+        (
+            self.hp_current - before.0,
+            self.mp_current - before.1,
+            self.ailments.len() < before.2,
+        )
+    }
     pub fn heal2max(&mut self) {
         self.hp_current = self.hp_max;
         self.mp_current = self.mp_max;
     }
-    pub fn add_exp(&mut self, exp: i32, inventory: &Inventory) -> bool {
-        self.exp = (&self.exp + exp).clamp(1, 999);
-        let new_lv = LEVEL_LIST.iter().filter(|&&e| e <= self.exp).count() as i32;
-        if self.lv != new_lv {
-            self.level_up(new_lv, inventory);
-            self.heal2max();
-            return true;
+    pub fn add_exp(
+        &mut self,
+        exp: i32,
+        growth: &Growth,
+        inventory: &mut Inventory,
+    ) -> Option<LevelUp> {
+        self.exp = (self.exp + exp).clamp(1, MAX_EXP);
+        let new_lv = growth.level(self.exp);
+        if self.lv == new_lv {
+            return None;
         }
-        return false;
+        let before = self.clone();
+        self.level_up(new_lv, growth, inventory);
+        self.heal2max();
+        let gains = [
+            ("Attack", before.attack, self.attack),
+            ("Defence", before.defence, self.defence),
+            ("Speed", before.speed, self.speed),
+            ("HP", before.hp_max, self.hp_max),
+            ("MP", before.mp_max, self.mp_max),
+            ("Accuracy", before.accuracy, self.accuracy),
+            ("Evasion", before.evasion, self.evasion),
+            ("Critical", before.critical, self.critical),
+        ]
+        .iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(stat, before, after)| (*stat, after - before))
+        .collect();
+        // 町で見つけて覚えている呪文は飛ばす
+        let learned = growth
+            .learned(&self.name, before.lv, self.lv)
+            .into_iter()
+            .filter_map(|spell| inventory.learn(spell))
+            .collect();
+        Some(LevelUp {
+            name: self.name.clone(),
+            lv: self.lv,
+            gains,
+            learned,
+        })
     }
-    pub fn level_up(&mut self, new_level: i32, inventory: &Inventory) {
+    pub fn level_up(&mut self, new_level: i32, growth: &Growth, inventory: &Inventory) {
         self.lv = new_level;
-        self.attack = 10 + (self.lv - 1) * 5;
-        self.defence = 10 + (self.lv - 1) * 5;
-        self.hp_max = 100 + (self.lv - 1) * 25;
-        self.mp_max = 100 + (self.lv - 1) * 25;
-
-        for item in inventory.items.iter() {
-            match item {
-                Item::IronBody => self.hp_max = (self.hp_max as f32 * 1.3) as i32,
-                Item::IronArm => self.hp_max = (self.hp_max as f32 * 1.3) as i32,
-                Item::IronLeg => self.hp_max = (self.hp_max as f32 * 1.3) as i32,
-                Item::IronHead => self.hp_max = (self.hp_max as f32 * 1.3) as i32,
-                Item::HeroSword => self.attack = (self.attack as f32 * 2.5) as i32,
-                Item::WisdomRing => self.mp_max = (self.mp_max as f32 * 2.5) as i32,
-                Item::FairyShield => self.defence = (self.defence as f32 * 2.5) as i32,
-                _ => {}
-            }
-        }
+        self.update_stats(growth, &inventory.equipment);
+    }
+    // Stats of the level from the growth curves plus the bonuses of the equipped gear
+    pub fn update_stats(&mut self, growth: &Growth, equipment: &Equipment) {
+        let curves = &growth.stats;
+        let lv = self.lv;
+        let bonus = equipment.bonus();
+        self.attack = curves.attack.at(lv) + bonus.attack;
+        self.defence = curves.defence.at(lv) + bonus.defence;
+        self.speed = curves.speed.at(lv) + bonus.speed;
+        self.accuracy = curves.accuracy.at(lv);
+        self.evasion = curves.evasion.at(lv);
+        self.critical = curves.critical.at(lv) + bonus.critical;
+        self.hp_max = curves.hp.at(lv) + bonus.hp;
+        self.mp_max = curves.mp.at(lv) + bonus.mp;
 
         self.attack = self.attack.clamp(1, 999);
         self.defence = self.defence.clamp(1, 999);
+        self.speed = self.speed.clamp(1, 999);
         self.hp_max = self.hp_max.clamp(1, 999);
         self.mp_max = self.mp_max.clamp(1, 999);
-        self.hp_current = self.hp_current.clamp(1, 999);
-        self.mp_current = self.mp_current.clamp(1, 999);
+        self.hp_current = self.hp_current.clamp(0, self.hp_max);
+        self.mp_current = self.mp_current.clamp(0, self.mp_max);
+    }
+}
+
+// What a level up raised, shown on the Win screen
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelUp {
+    pub name: String,
+    pub lv: i32,
+    // e.g. ("Attack", 5)
+    pub gains: Vec<(&'static str, i32)>,
+    // spells of the learn table new to the hero
+    pub learned: Vec<Skill>,
+}
+
+impl Display for LevelUp {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let gains = self
+            .gains
+            .iter()
+            .map(|(stat, gain)| format!("{} {:+}", stat, gain))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(fmt, "{} reached Lv {}!\n{}", self.name, self.lv, gains)?;
+        for skill in self.learned.iter() {
+            write!(fmt, "\n{} learned {}!", self.name, skill)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Skill {
     Sword,
     Spell(Item),
     Arrow,
     Wind,
     Death,
+    Bite,
+    Curse,
+    // a consumable from the inventory
+    Item(Item),
+    // battle commands every hero has, never stored in the inventory
+    Defend,
+    Run,
+}
+
+// Who a skill is used on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SkillTarget {
+    Myself,
+    Ally,
+    Enemy,
+    AllEnemies,
+}
+
+impl Skill {
+    pub fn target(&self) -> SkillTarget {
+        match self {
+            Skill::Spell(Item::SpellHeal(_))
+            | Skill::Spell(Item::SpellCure(_))
+            | Skill::Item(_) => SkillTarget::Ally,
+            // battle_events handles each of these by name
+            Skill::Defend | Skill::Run => SkillTarget::Myself,
+            Skill::Spell(Item::SpellIce(_)) => SkillTarget::AllEnemies,
+            _ => SkillTarget::Enemy,
+        }
+    }
+    // Items used from the battle menu are not spells
+    pub fn is_spell(&self) -> bool {
+        matches!(self, Skill::Spell(_))
+    }
+    // (ailment, chance, turns) the skill may inflict on a hit
+    pub fn ailment(&self) -> Option<(Ailment, f64, i32)> {
+        match self {
+            Skill::Bite => Some((Ailment::Poison, 0.3, 10)),
+            Skill::Curse => Some((Ailment::Silence, 0.3, 4)),
+            Skill::Wind => Some((Ailment::Sleep, 0.2, 2)),
+            Skill::Death => Some((Ailment::Paralysis, 0.3, 2)),
+            Skill::Spell(Item::SpellIce(_)) => Some((Ailment::Paralysis, 0.2, 1)),
+            _ => None,
+        }
+    }
 }
 
 impl Display for Skill {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
-            Skill::Spell(item) => {
+            Skill::Spell(item) | Skill::Item(item) => {
                 write!(f, "{}", item)
             }
             _ => {
@@ -128,3 +385,44 @@ impl Display for Skill {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hero(hp_current: i32, ailments: Vec<(Ailment, i32)>) -> CharacterStatus {
+        CharacterStatus {
+            hp_current,
+            hp_max: 150,
+            ailments,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn a_herb_restores_only_the_missing_hp() {
+        let mut status = hero(130, vec![]);
+        assert_eq!(status.use_item(&Item::Herb), (20, 0, false));
+        assert_eq!(status.hp_current, 150);
+    }
+
+    #[test]
+    fn a_herb_does_nothing_at_full_hp() {
+        let mut status = hero(150, vec![]);
+        assert_eq!(status.use_item(&Item::Herb), (0, 0, false));
+    }
+
+    #[test]
+    fn an_antidote_cures_poison() {
+        let mut status = hero(150, vec![(Ailment::Poison, 3)]);
+        assert_eq!(status.use_item(&Item::Antidote), (0, 0, true));
+        assert!(status.ailments.is_empty());
+    }
+
+    #[test]
+    fn an_antidote_does_nothing_to_a_healthy_hero() {
+        let mut status = hero(150, vec![(Ailment::Sleep, 2)]);
+        assert_eq!(status.use_item(&Item::Antidote), (0, 0, false));
+        assert_eq!(status.ailments, vec![(Ailment::Sleep, 2)]);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::map::{Field, Map, MAP_SIZE};
+use crate::world_map::WorldMapAsset;
+use crate::AppState;
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+
+pub struct DungeonPlugin;
+
+// This plugin keeps track of the dungeon the player is exploring.
+// Floors replace the world map resource while the player is inside.
+impl Plugin for DungeonPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Dungeon>().add_system_set(
+            SystemSet::on_enter(AppState::Menu).with_system(clean_up_dungeon.system()),
+        );
+    }
+}
+
+pub const DUNGEON_FLOORS: usize = 3;
+const ROOMS_PER_FLOOR: usize = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DungeonTransition {
+    Enter,
+    Up,
+    Down,
+}
+
+pub struct DungeonFloor {
+    pub world_map: WorldMapAsset,
+    // stairs leading to the floor above (or the Lich on the top floor) and below (or outside)
+    pub up: (i32, i32),
+    pub down: (i32, i32),
+}
+
+#[derive(Default)]
+pub struct Dungeon {
+    pub floors: Vec<DungeonFloor>,
+    // None while on the world map
+    pub floor: Option<usize>,
+    // world map kept aside while inside the dungeon
+    pub world: Option<Map>,
+    pub world_position: (i32, i32),
+    // applied by the map plugin when entering AppState::InGameMap
+    pub transition: Option<DungeonTransition>,
+}
+
+impl Dungeon {
+    pub fn is_inside(&self) -> bool {
+        self.floor.is_some()
+    }
+
+    // Swap the map resource for the next floor or back to the world map
+    pub fn apply(&mut self, transition: DungeonTransition, map: &mut Map) {
+        match (transition, self.floor) {
+            (DungeonTransition::Enter, _) => {
+                self.floors = generate_floors(map.seed);
+                self.world = Some(std::mem::take(map));
+                self.enter_floor(0, true, map);
+            }
+            (DungeonTransition::Up, Some(floor)) if floor + 1 < self.floors.len() => {
+                self.enter_floor(floor + 1, true, map);
+            }
+            (DungeonTransition::Down, Some(floor)) if floor > 0 => {
+                self.enter_floor(floor - 1, false, map);
+            }
+            (DungeonTransition::Down, Some(_floor)) => {
+                let mut world = self.world.take().expect("world map is kept while inside");
+                world.move_start(self.world_position);
+                *map = world;
+                self.floor = None;
+                self.floors.clear();
+            }
+            (transition, floor) => {
+                warn!("ignoring {:?} on floor {:?}", transition, floor);
+            }
+        }
+    }
+
+    // Arriving from below starts on the down stairs, from above on the up stairs
+    fn enter_floor(&mut self, floor: usize, from_below: bool, map: &mut Map) {
+        let dungeon_floor = &self.floors[floor];
+        let mut world_map = dungeon_floor.world_map.clone();
+        world_map.start = if from_below {
+            dungeon_floor.down
+        } else {
+            dungeon_floor.up
+        };
+        let source = match &self.world {
+            Some(world) => world.source,
+            None => map.source,
+        };
+        *map = Map::from_world_map(map.seed, source, world_map);
+        map.wrap = false;
+        self.floor = Some(floor);
+    }
+}
+
+// Rooms connected by corridors, the same world seed always gives the same dungeon
+pub fn generate_floors(seed: u64) -> Vec<DungeonFloor> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed ^ 0x6475_6e67_656f_6e73);
+    (0..DUNGEON_FLOORS)
+        .map(|floor| generate_floor(&mut rng, floor + 1 == DUNGEON_FLOORS))
+        .collect()
+}
+
+struct Room {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+impl Room {
+    fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+    fn overlaps(&self, other: &Room) -> bool {
+        // keep one wall between rooms
+        self.x - 1 <= other.x + other.width
+            && other.x - 1 <= self.x + self.width
+            && self.y - 1 <= other.y + other.height
+            && other.y - 1 <= self.y + self.height
+    }
+}
+
+fn generate_floor(rng: &mut ChaCha8Rng, top: bool) -> DungeonFloor {
+    let width = MAP_SIZE[0] as i32;
+    let height = MAP_SIZE[1] as i32;
+    let mut fields = HashMap::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            fields.insert((x - width / 2, y - height / 2), Field::Wall);
+        }
+    }
+
+    // the outermost tiles stay walls so the floor never wraps around
+    let mut rooms: Vec<Room> = Vec::new();
+    for _ in 0..ROOMS_PER_FLOOR * 20 {
+        if rooms.len() == ROOMS_PER_FLOOR {
+            break;
+        }
+        let room_width = rng.gen_range(4..10);
+        let room_height = rng.gen_range(3..8);
+        let room = Room {
+            x: rng.gen_range(1..width - room_width - 1) - width / 2,
+            y: rng.gen_range(1..height - room_height - 1) - height / 2,
+            width: room_width,
+            height: room_height,
+        };
+        if rooms.iter().all(|other| !room.overlaps(other)) {
+            rooms.push(room);
+        }
+    }
+
+    if rooms.len() < 2 {
+        rooms = vec![
+            Room {
+                x: -width / 4,
+                y: -2,
+                width: 4,
+                height: 4,
+            },
+            Room {
+                x: width / 4,
+                y: -2,
+                width: 4,
+                height: 4,
+            },
+        ];
+    }
+
+    for room in rooms.iter() {
+        for y in room.y..room.y + room.height {
+            for x in room.x..room.x + room.width {
+                fields.insert((x, y), Field::Floor);
+            }
+        }
+    }
+    for pair in rooms.windows(2) {
+        let (from, to) = (pair[0].center(), pair[1].center());
+        let corner = if rng.gen_bool(0.5) {
+            (to.0, from.1)
+        } else {
+            (from.0, to.1)
+        };
+        dig_corridor(&mut fields, from, corner);
+        dig_corridor(&mut fields, corner, to);
+    }
+
+    let down = rooms[0].center();
+    let up = rooms[rooms.len() - 1].center();
+    fields.insert(down, Field::StairsDown);
+    fields.insert(up, if top { Field::Throne } else { Field::StairsUp });
+
+    DungeonFloor {
+        world_map: WorldMapAsset {
+            fields,
+            start: down,
+            ..Default::default()
+        },
+        up,
+        down,
+    }
+}
+
+fn dig_corridor(fields: &mut HashMap<(i32, i32), Field>, from: (i32, i32), to: (i32, i32)) {
+    let (mut x, mut y) = from;
+    loop {
+        if matches!(fields.get(&(x, y)), Some(Field::Wall)) {
+            fields.insert((x, y), Field::Floor);
+        }
+        if (x, y) == to {
+            break;
+        }
+        if x != to.0 {
+            x += (to.0 - x).signum();
+        } else {
+            y += (to.1 - y).signum();
+        }
+    }
+}
+
+fn clean_up_dungeon(mut dungeon: ResMut<Dungeon>) {
+    *dungeon = Dungeon::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::unreachable_fields;
+
+    #[test]
+    fn every_floor_can_be_climbed_to_the_throne() {
+        for seed in 0..1000 {
+            let floors = generate_floors(seed);
+            assert_eq!(floors.len(), DUNGEON_FLOORS);
+            for (floor, dungeon_floor) in floors.iter().enumerate() {
+                let fields = &dungeon_floor.world_map.fields;
+                let top = if floor + 1 == DUNGEON_FLOORS {
+                    Field::Throne
+                } else {
+                    Field::StairsUp
+                };
+                assert_eq!(fields[&dungeon_floor.up], top, "seed {}", seed);
+                assert_eq!(fields[&dungeon_floor.down], Field::StairsDown);
+                assert_eq!(
+                    unreachable_fields(fields, dungeon_floor.down),
+                    vec![],
+                    "seed {} floor {}",
+                    seed,
+                    floor + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn floors_do_not_wrap_around() {
+        let width = MAP_SIZE[0] as i32;
+        let height = MAP_SIZE[1] as i32;
+        for dungeon_floor in generate_floors(7).iter() {
+            for (pos, field) in dungeon_floor.world_map.fields.iter() {
+                let edge = pos.0 == -width / 2
+                    || pos.0 == width / 2 - 1
+                    || pos.1 == -height / 2
+                    || pos.1 == height / 2 - 1;
+                if edge {
+                    assert_eq!(*field, Field::Wall, "{:?}", pos);
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::audio::{AudioEvent, AudioKind};
-use crate::character_status::Skill;
-use crate::enemies::{Battle, Enemy};
+use crate::character_status::{Ailment, Skill};
+use crate::enemies::{enemy_screen_x, Battle, Enemy, EnemySlot};
 use crate::inventory::Item;
 use crate::loading::{EffectsAtlas, FontAssets};
 use crate::player::{Player, PlayerBattleState};
@@ -35,31 +35,78 @@ pub enum EffectKind {
     Death,
     Arrow,
     Wind,
+    Defend,
+    Cure,
+    // poison damage, or a turn lost to sleep or paralysis
+    Ailment(Ailment),
 }
 
 pub struct EffectEvent {
     pub kind: EffectKind,
     pub damage_or_heal: i32,
     pub is_player_attack: bool,
+    // slot of the enemy the effect is drawn on, None for the player
+    pub target: Option<usize>,
+    pub hit: Hit,
+}
+
+// How a hit landed, shown under the damage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hit {
+    Normal,
+    // the target is weak to the element
+    Weak,
+    // the target resists the element
+    Resisted,
+    // ignored the defence of the target
+    Critical,
+    Miss,
+}
+
+impl Hit {
+    fn color(&self) -> Color {
+        match self {
+            Hit::Critical => Color::rgb(1., 0.8, 0.),
+            _ => Color::WHITE,
+        }
+    }
+    fn text(&self) -> &'static str {
+        match self {
+            Hit::Normal | Hit::Miss => "",
+            Hit::Critical => "\nCritical!",
+            Hit::Weak => "\nIt's super effective!",
+            Hit::Resisted => "\nResisted",
+        }
+    }
 }
 
 pub struct Effect {
     finish_timer: Timer,
     update_timer: Timer,
+    target: Option<usize>,
 }
 
 pub struct EffectString;
 
 pub fn skill_to_effect(skill: Skill) -> EffectKind {
     match skill {
-        Skill::Sword => EffectKind::Attack,
+        Skill::Sword | Skill::Bite => EffectKind::Attack,
+        Skill::Curse => EffectKind::Death,
         Skill::Wind => EffectKind::Wind,
         Skill::Arrow => EffectKind::Arrow,
         Skill::Death => EffectKind::Death,
+        Skill::Defend => EffectKind::Defend,
+        Skill::Run => panic!("running has no effect."),
         Skill::Spell(item) => match item {
             Item::SpellHeal(_) => EffectKind::Heal,
             Item::SpellFire(_) => EffectKind::Fire,
             Item::SpellIce(_) => EffectKind::Ice,
+            Item::SpellCure(_) => EffectKind::Cure,
+            _ => panic!("select item cannot use."),
+        },
+        Skill::Item(item) => match item {
+            Item::Herb | Item::MagicWater => EffectKind::Heal,
+            Item::Antidote => EffectKind::Cure,
             _ => panic!("select item cannot use."),
         },
     }
@@ -79,15 +126,33 @@ fn effects_event(
     for event in event_reader.iter() {
         let texture_atlas_handle = texture_atlas.get_handle_for_effect(&event.kind);
         let effect_length = texture_atlas.get_length_for_effect(&event.kind);
+        // 自分への効果は敵の並びの中央に表示する
+        let (position, text_left, text_width, scale) = match event.target {
+            Some(slot) => {
+                let count = battle.enemies.len();
+                (
+                    battle.enemy_positions[slot],
+                    enemy_screen_x(slot, count) - 33. / count as f32,
+                    66. / count as f32,
+                    battle.enemy_scale,
+                )
+            }
+            None => {
+                let first = battle.enemy_positions[0];
+                let last = battle.enemy_positions[battle.enemy_positions.len() - 1];
+                ((first + last) / 2., 34., 66., 1.)
+            }
+        };
         commands
             .spawn_bundle(SpriteSheetBundle {
                 texture_atlas: texture_atlas_handle,
                 transform: Transform {
                     translation: Vec3::new(
-                        battle.enemy_root_offset.x,
-                        battle.enemy_root_offset.y,
+                        position.x,
+                        position.y,
                         render_layer(RenderLayer::BattleEffect) as f32,
                     ),
+                    scale: Vec3::new(scale, scale, 1.),
                     ..Default::default()
                 },
                 ..Default::default()
@@ -95,15 +160,24 @@ fn effects_event(
             .insert(Effect {
                 finish_timer: Timer::from_seconds(effect_length as f32 * 0.1, false),
                 update_timer: Timer::from_seconds(0.1, true),
+                target: event.target,
             })
             .insert(ForState {
                 states: vec![AppState::InGameBattle],
             })
             .with_children(|child_builder| {
-                if !&event.is_player_attack {
+                if !&event.is_player_attack
+                    && !matches!(
+                        event.kind,
+                        EffectKind::Defend | EffectKind::Heal | EffectKind::Ailment(_)
+                    )
+                {
                     child_builder
                         .spawn_bundle(SpriteBundle {
-                            sprite: Sprite::new(Vec2::new(window.width(), window.height())),
+                            sprite: Sprite::new(Vec2::new(
+                                window.width() / scale,
+                                window.height() / scale,
+                            )),
                             material: materials.add(Color::rgba(1., 0., 0., 0.1).into()),
                             transform: Transform {
                                 translation: Vec3::new(0., 0., -5.),
@@ -121,10 +195,10 @@ fn effects_event(
         commands
             .spawn_bundle(NodeBundle {
                 style: Style {
-                    size: Size::new(Val::Percent(35.), Val::Percent(100.)),
+                    size: Size::new(Val::Percent(text_width), Val::Percent(100.)),
                     position_type: PositionType::Absolute,
                     position: Rect {
-                        right: Val::Percent(0.),
+                        left: Val::Percent(text_left),
                         top: Val::Percent(0.),
                         ..Default::default()
                     },
@@ -138,6 +212,7 @@ fn effects_event(
             .insert(Effect {
                 finish_timer: Timer::from_seconds(effect_length as f32 * 0.1, false),
                 update_timer: Timer::from_seconds(0.1, true),
+                target: event.target,
             })
             .insert(EffectString)
             .insert(ForState {
@@ -150,27 +225,59 @@ fn effects_event(
                         justify_content: JustifyContent::Center,
                         ..Default::default()
                     },
-                    text: Text::with_section(
-                        format!("{:?}", event.damage_or_heal),
-                        TextStyle {
-                            font: font_assets.pixel_mplus.clone(),
-                            font_size: 120.0,
-                            color: Color::WHITE,
-                        },
-                        TextAlignment {
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: match event.kind {
+                                    EffectKind::Defend if !event.is_player_attack => {
+                                        "Guard".to_string()
+                                    }
+                                    EffectKind::Cure => "Cured".to_string(),
+                                    EffectKind::Ailment(ailment) if event.damage_or_heal == 0 => {
+                                        ailment.to_string()
+                                    }
+                                    _ if event.hit == Hit::Miss => "Miss!".to_string(),
+                                    _ => format!("{:?}", event.damage_or_heal),
+                                },
+                                style: TextStyle {
+                                    font: font_assets.pixel_mplus.clone(),
+                                    font_size: (120. * scale).max(60.),
+                                    color: event.hit.color(),
+                                },
+                            },
+                            // 弱点・耐性は小さく添える
+                            TextSection {
+                                value: event.hit.text().to_string(),
+                                style: TextStyle {
+                                    font: font_assets.pixel_mplus.clone(),
+                                    font_size: (40. * scale).max(20.),
+                                    color: event.hit.color(),
+                                },
+                            },
+                        ],
+                        alignment: TextAlignment {
                             vertical: VerticalAlign::Center,
                             horizontal: HorizontalAlign::Center,
                         },
-                    ),
+                    },
                     ..Default::default()
                 });
             });
-        if matches!(&event.is_player_attack, true) {
-            if matches!(&event.kind, EffectKind::Heal) {
-                audio_event_writer.send(AudioEvent::Play(AudioKind::SEHeal));
-            } else {
-                audio_event_writer.send(AudioEvent::Play(AudioKind::SEAttack));
+        // 会心の一撃はどちらの攻撃でも専用の音を鳴らし、空振りは鳴らさない
+        match event.hit {
+            Hit::Critical => audio_event_writer.send(AudioEvent::Play(AudioKind::SECritical)),
+            Hit::Miss => {}
+            _ if event.is_player_attack => {
+                if matches!(
+                    &event.kind,
+                    EffectKind::Heal | EffectKind::Defend | EffectKind::Cure
+                ) {
+                    audio_event_writer.send(AudioEvent::Play(AudioKind::SEHeal));
+                } else {
+                    audio_event_writer.send(AudioEvent::Play(AudioKind::SEAttack));
+                }
             }
+            _ => {}
         }
     }
 }
@@ -189,47 +296,59 @@ fn handle_effect(
         Query<(Entity, &mut Effect, &EffectString)>,
     )>,
     mut player_query: Query<&mut Player>,
-    mut enemy_query: Query<&mut Transform, With<Enemy>>,
+    mut enemy_query: Query<(&mut Transform, &EnemySlot), With<Enemy>>,
 ) {
     let elapsed = time.delta();
     let mut rng = rand::thread_rng();
-    for mut enemy_transform in enemy_query.iter_mut() {
-        for mut player in player_query.iter_mut() {
-            for (entity, mut effect, mut sprite, texture_atlas_handle) in query.q0_mut().iter_mut()
-            {
-                effect.update_timer.tick(elapsed);
-                if effect.update_timer.finished() {
-                    let texture_atlas = texture_atlases.get(texture_atlas_handle).unwrap();
-                    sprite.index =
-                        ((sprite.index as usize + 1) % texture_atlas.textures.len()) as u32;
-                }
+    let mut finished = 0;
+    let mut running = 0;
+    for (entity, mut effect, mut sprite, texture_atlas_handle) in query.q0_mut().iter_mut() {
+        effect.update_timer.tick(elapsed);
+        if effect.update_timer.finished() {
+            let texture_atlas = texture_atlases.get(texture_atlas_handle).unwrap();
+            sprite.index = ((sprite.index as usize + 1) % texture_atlas.textures.len()) as u32;
+        }
+        effect.finish_timer.tick(elapsed);
+        let done = effect.finish_timer.finished();
+        // 対象の敵を揺らす
+        for (mut enemy_transform, slot) in enemy_query.iter_mut() {
+            if effect.target != Some(slot.0) {
+                continue;
+            }
+            if done {
+                // Enemyを一旦止める
+                enemy_transform.translation.x = 0.;
+                enemy_transform.translation.y = 0.;
+            } else {
                 enemy_transform.translation.x = rng.gen_range(-1.0..1.0);
                 enemy_transform.translation.y = rng.gen_range(-1.0..1.0);
-
-                effect.finish_timer.tick(elapsed);
-                if effect.finish_timer.finished() {
-                    commands.entity(entity).despawn_recursive();
-                    match player.battle_state {
-                        PlayerBattleState::Attack => {
-                            player.battle_state = PlayerBattleState::Defense
-                        }
-                        PlayerBattleState::Defense => {
-                            player.battle_state = PlayerBattleState::Select
-                        }
-                        _ => info!("unexpected effect"),
-                    }
-                    // Enemyを一旦止める
-                    enemy_transform.translation.x = 0.;
-                    enemy_transform.translation.y = 0.;
-                } else {
-                }
             }
-            for (entity, mut effect, _string) in query.q1_mut().iter_mut() {
-                effect.finish_timer.tick(elapsed);
-                if effect.finish_timer.finished() {
-                    commands.entity(entity).despawn_recursive();
-                }
+        }
+        if done {
+            commands.entity(entity).despawn_recursive();
+            finished += 1;
+        } else {
+            running += 1;
+        }
+    }
+    for (entity, mut effect, _string) in query.q1_mut().iter_mut() {
+        effect.finish_timer.tick(elapsed);
+        if effect.finish_timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    // 全体攻撃は全てのエフェクトが終わってから次へ進む
+    if finished == 0 || running > 0 {
+        return;
+    }
+    for mut player in player_query.iter_mut() {
+        match player.battle_state {
+            // 次の者の番
+            PlayerBattleState::Attack | PlayerBattleState::NextTurn => {
+                player.battle_state = PlayerBattleState::NextTurn
             }
+            _ => info!("unexpected effect"),
         }
     }
 }
@@ -0,0 +1,145 @@
+use crate::character_status::Skill;
+use crate::effects::Hit;
+use crate::inventory::Item;
+use bevy::prelude::*;
+use std::array::IntoIter;
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+pub struct ElementPlugin;
+
+// This plugin holds which element every skill belongs to
+impl Plugin for ElementPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ElementData>();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Element {
+    Physical,
+    Fire,
+    Ice,
+    Wind,
+    Dark,
+    Holy,
+}
+
+pub struct ElementData {
+    pub skills: HashMap<Skill, Element>,
+}
+impl Default for ElementData {
+    fn default() -> Self {
+        use Element::*;
+        ElementData {
+            skills: HashMap::<_, _>::from_iter(IntoIter::new([
+                (Skill::Sword, Physical),
+                (Skill::Bite, Physical),
+                (Skill::Arrow, Physical),
+                (Skill::Wind, Wind),
+                (Skill::Curse, Dark),
+                (Skill::Death, Dark),
+                (Skill::Spell(Item::SpellFire(1)), Fire),
+                (Skill::Spell(Item::SpellFire(2)), Fire),
+                (Skill::Spell(Item::SpellFire(3)), Fire),
+                (Skill::Spell(Item::SpellIce(1)), Ice),
+                (Skill::Spell(Item::SpellIce(2)), Ice),
+                (Skill::Spell(Item::SpellIce(3)), Ice),
+                (Skill::Spell(Item::SpellHeal(1)), Holy),
+                (Skill::Spell(Item::SpellHeal(2)), Holy),
+                (Skill::Spell(Item::SpellHeal(3)), Holy),
+            ])),
+        }
+    }
+}
+
+impl ElementData {
+    pub fn element(&self, skill: &Skill) -> Element {
+        self.skills.get(skill).copied().unwrap_or(Element::Physical)
+    }
+}
+
+// Damage multipliers of an enemy, elements not listed deal normal damage
+#[derive(Clone, Debug, Default)]
+pub struct Affinities(pub Vec<(Element, f32)>);
+
+impl Affinities {
+    pub fn multiplier(&self, element: Element) -> f32 {
+        self.0
+            .iter()
+            .find(|(e, _)| *e == element)
+            .map(|(_, multiplier)| *multiplier)
+            .unwrap_or(1.)
+    }
+    // A multiplier of 0 makes the enemy take no damage at all
+    pub fn immune(&self, element: Element) -> bool {
+        self.multiplier(element) <= 0.
+    }
+    // Damage of the element and how the hit is shown
+    pub fn scale(&self, element: Element, damage: i32) -> (i32, Hit) {
+        if self.immune(element) {
+            return (0, Hit::Resisted);
+        }
+        let multiplier = self.multiplier(element);
+        let hit = if multiplier > 1. {
+            Hit::Weak
+        } else if multiplier < 1. {
+            Hit::Resisted
+        } else {
+            Hit::Normal
+        };
+        ((damage as f32 * multiplier) as i32, hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weakness_scales_damage_up() {
+        let affinities = Affinities(vec![(Element::Fire, 2.)]);
+        assert_eq!(affinities.scale(Element::Fire, 30), (60, Hit::Weak));
+    }
+
+    #[test]
+    fn resistance_scales_damage_down() {
+        let affinities = Affinities(vec![(Element::Wind, 0.5)]);
+        assert_eq!(affinities.scale(Element::Wind, 30), (15, Hit::Resisted));
+    }
+
+    #[test]
+    fn immunity_blocks_all_damage() {
+        let affinities = Affinities(vec![(Element::Dark, 0.)]);
+        assert!(affinities.immune(Element::Dark));
+        assert!(!affinities.immune(Element::Fire));
+        assert_eq!(affinities.scale(Element::Dark, 30), (0, Hit::Resisted));
+        assert_eq!(affinities.scale(Element::Dark, -3), (0, Hit::Resisted));
+    }
+
+    #[test]
+    fn other_elements_deal_normal_damage() {
+        let affinities = Affinities(vec![(Element::Fire, 2.)]);
+        assert_eq!(affinities.scale(Element::Ice, 30), (30, Hit::Normal));
+        assert_eq!(
+            Affinities::default().scale(Element::Dark, 30),
+            (30, Hit::Normal)
+        );
+    }
+
+    #[test]
+    fn spells_of_every_level_share_their_element() {
+        let data = ElementData::default();
+        for lv in 1..=3 {
+            assert_eq!(
+                data.element(&Skill::Spell(Item::SpellFire(lv))),
+                Element::Fire
+            );
+            assert_eq!(
+                data.element(&Skill::Spell(Item::SpellIce(lv))),
+                Element::Ice
+            );
+        }
+        assert_eq!(data.element(&Skill::Sword), Element::Physical);
+    }
+}
@@ -1,6 +1,6 @@
 use core::fmt;
 use std::array::IntoIter;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Display;
 use std::iter::FromIterator;
 
@@ -8,12 +8,19 @@ use bevy::prelude::*;
 use rand::Rng;
 
 use crate::audio::{AudioEvent, AudioKind};
+use crate::boss::{Phase, Phases};
 use crate::character_status::{CharacterStatus, Skill};
 use crate::effects::{Effect, EffectString};
+use crate::element::{Affinities, Element};
+use crate::enemy_ai::{Condition, EnemyAi, SkillChoice};
+use crate::events::{GameEvent, RunState};
+use crate::inventory::Item;
 use crate::loading::TextureAssets;
-use crate::map::{Field, Map, Position};
-use crate::player::Player;
+use crate::map::Field;
+use crate::party::{alive_members, PartyMember};
+use crate::player::{Player, PlayerBattleState};
 use crate::setup::{render_layer, ForState, MapCamera, RenderLayer};
+use crate::turn_order::Combatant;
 use crate::AppState;
 
 pub struct EnemiesPlugin;
@@ -31,20 +38,39 @@ impl Plugin for EnemiesPlugin {
     }
 }
 
+// State of the battle shared by the battle systems
 #[derive(Default)]
 pub struct Battle {
-    pub enemy: Enemy,
     pub entity: Option<Entity>,
-    pub enemy_status: Option<CharacterStatus>,
-    pub ui_status_text: Option<Text>,
-    pub enemy_root_offset: Vec2,
+    // enemy entities from left to right
+    pub enemies: Vec<Entity>,
+    // world positions of the enemies, effects are drawn on them
+    pub enemy_positions: Vec<Vec2>,
+    pub enemy_scale: f32,
+    // index into `enemies` chosen by the target cursor
+    pub target: usize,
+    // party member choosing or doing an action
+    pub actor: usize,
+    // party member chosen for a heal spell
+    pub ally_target: usize,
+    // false against the boss
+    pub can_run: bool,
+    // combatants that still act this round, fastest first
+    pub turns: VecDeque<Combatant>,
+    // lines a boss speaks, the battle waits until they are read
+    pub dialogue: VecDeque<String>,
 }
 
+// Position of an enemy in the battle, from left to right
 #[derive(Clone, Copy, Debug)]
+pub struct EnemySlot(pub usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Enemy {
     Goblin,
     Skeleton,
     Griffin,
+    Archer,
     Boss,
 }
 impl Default for Enemy {
@@ -60,68 +86,337 @@ impl Display for Enemy {
 
 #[derive(Clone, Copy, Debug)]
 pub struct EnemyStatus {
-    name: Enemy,
-    rate: i32,
-    img: usize,
     hp: i32,
     at: i32,
     df: i32,
-    skl: Skill,
+    sp: i32,
+    // accuracy, evasion and critical rate in percent
+    ac: i32,
+    ev: i32,
+    cr: i32,
+    // what it does on its turn, see enemy_ai
+    skills: &'static [SkillChoice],
+    // color the sprite is drawn with
+    tint: Color,
+    // damage multipliers of elements it is weak to or resists
+    affinities: &'static [(Element, f32)],
+    // later stages of the fight, see boss
+    phases: &'static [Phase],
+    // gold dropped per level
+    gold: u32,
+}
+
+// The Lich gets angry below 60% HP and desperate below 25%
+const LICH_PHASES: &[Phase] = &[
+    Phase {
+        below: 0.6,
+        skills: &[
+            SkillChoice {
+                skill: Skill::Death,
+                weight: 10,
+                condition: Condition::OncePerBattle,
+            },
+            SkillChoice {
+                skill: Skill::Spell(Item::SpellFire(2)),
+                weight: 5,
+                condition: Condition::EveryOtherTurn,
+            },
+            SkillChoice {
+                skill: Skill::Wind,
+                weight: 3,
+                condition: Condition::Always,
+            },
+            SkillChoice {
+                skill: Skill::Spell(Item::SpellHeal(2)),
+                weight: 6,
+                condition: Condition::HpBelow(0.3),
+            },
+        ],
+        tint: Color::rgb(1., 0.6, 0.6),
+        affinities: &[
+            (Element::Fire, 0.),
+            (Element::Ice, 0.5),
+            (Element::Dark, 0.),
+            (Element::Holy, 1.5),
+        ],
+        bgm: Some(AudioKind::BGMBattleLast),
+        lines: &[
+            "Lich: You fools... you dare to wound me?",
+            "The Lich is wrapped in flames!",
+        ],
+    },
+    Phase {
+        below: 0.25,
+        skills: &[
+            SkillChoice {
+                skill: Skill::Spell(Item::SpellIce(2)),
+                weight: 5,
+                condition: Condition::Always,
+            },
+            SkillChoice {
+                skill: Skill::Spell(Item::SpellFire(2)),
+                weight: 3,
+                condition: Condition::EveryOtherTurn,
+            },
+            SkillChoice {
+                skill: Skill::Sword,
+                weight: 2,
+                condition: Condition::Always,
+            },
+        ],
+        tint: Color::rgb(0.6, 0.6, 1.),
+        affinities: &[
+            (Element::Fire, 0.5),
+            (Element::Ice, 0.),
+            (Element::Dark, 0.),
+            (Element::Holy, 2.),
+        ],
+        bgm: None,
+        lines: &[
+            "Lich: This cannot be... I am eternal!",
+            "The flames die down and a freezing cold fills the room.",
+        ],
+    },
+];
+
+// Which groups of enemies appear on a field and how often
+#[derive(Clone, Debug)]
+pub struct EncounterTable {
+    // 1 / rate chance per step
+    pub rate: i32,
+    pub groups: Vec<Vec<Enemy>>,
 }
 
 pub struct EnemyData {
-    pub data: HashMap<Field, EnemyStatus>,
+    pub enemies: HashMap<Enemy, EnemyStatus>,
+    pub tables: HashMap<Field, EncounterTable>,
 }
 impl Default for EnemyData {
     fn default() -> Self {
+        use Condition::*;
+        use Enemy::*;
         EnemyData {
-            data: HashMap::<_, _>::from_iter(IntoIter::new([
+            enemies: HashMap::<_, _>::from_iter(IntoIter::new([
                 (
-                    Field::Grass,
+                    Goblin,
                     EnemyStatus {
-                        name: Enemy::Goblin,
-                        rate: 20,
-                        img: 0,
                         hp: 50,
                         at: 10,
                         df: 5,
-                        skl: Skill::Sword,
+                        sp: 8,
+                        ac: 90,
+                        ev: 5,
+                        cr: 3,
+                        skills: &[
+                            SkillChoice {
+                                skill: Skill::Bite,
+                                weight: 6,
+                                condition: Always,
+                            },
+                            SkillChoice {
+                                skill: Skill::Sword,
+                                weight: 3,
+                                condition: Always,
+                            },
+                            SkillChoice {
+                                skill: Skill::Defend,
+                                weight: 1,
+                                condition: Always,
+                            },
+                        ],
+                        tint: Color::WHITE,
+                        affinities: &[],
+                        phases: &[],
+                        gold: 5,
                     },
                 ),
                 (
-                    Field::Forest,
+                    Skeleton,
                     EnemyStatus {
-                        name: Enemy::Skeleton,
-                        rate: 10,
-                        img: 1,
                         hp: 100,
                         at: 20,
                         df: 10,
-                        skl: Skill::Sword,
+                        sp: 6,
+                        ac: 85,
+                        ev: 0,
+                        cr: 3,
+                        skills: &[
+                            SkillChoice {
+                                skill: Skill::Curse,
+                                weight: 3,
+                                condition: Always,
+                            },
+                            SkillChoice {
+                                skill: Skill::Sword,
+                                weight: 4,
+                                condition: Always,
+                            },
+                            SkillChoice {
+                                skill: Skill::Defend,
+                                weight: 2,
+                                condition: Always,
+                            },
+                        ],
+                        tint: Color::WHITE,
+                        affinities: &[
+                            (Element::Fire, 2.),
+                            (Element::Holy, 2.),
+                            (Element::Dark, 0.5),
+                        ],
+                        phases: &[],
+                        gold: 12,
                     },
                 ),
                 (
-                    Field::Mountain,
+                    Griffin,
                     EnemyStatus {
-                        name: Enemy::Griffin,
-                        rate: 5,
-                        img: 2,
                         hp: 200,
                         at: 40,
                         df: 30,
-                        skl: Skill::Wind,
+                        sp: 25,
+                        ac: 95,
+                        ev: 20,
+                        cr: 5,
+                        skills: &[
+                            SkillChoice {
+                                skill: Skill::Sword,
+                                weight: 3,
+                                condition: Always,
+                            },
+                            SkillChoice {
+                                skill: Skill::Wind,
+                                weight: 4,
+                                condition: EveryOtherTurn,
+                            },
+                        ],
+                        tint: Color::WHITE,
+                        affinities: &[(Element::Ice, 1.5), (Element::Wind, 0.5)],
+                        phases: &[],
+                        gold: 15,
                     },
                 ),
                 (
-                    Field::Castle,
+                    Archer,
+                    EnemyStatus {
+                        hp: 70,
+                        at: 18,
+                        df: 8,
+                        sp: 12,
+                        ac: 95,
+                        ev: 10,
+                        cr: 8,
+                        skills: &[
+                            SkillChoice {
+                                skill: Skill::Arrow,
+                                weight: 4,
+                                condition: Always,
+                            },
+                            SkillChoice {
+                                skill: Skill::Defend,
+                                weight: 1,
+                                condition: Always,
+                            },
+                        ],
+                        // 骸骨の絵を緑がかった色で使う
+                        tint: Color::rgb(0.6, 1., 0.6),
+                        affinities: &[(Element::Fire, 1.5), (Element::Wind, 1.5)],
+                        phases: &[],
+                        gold: 10,
+                    },
+                ),
+                (
+                    Boss,
                     EnemyStatus {
-                        name: Enemy::Boss,
-                        rate: 1,
-                        img: 3,
                         hp: 999,
                         at: 99,
                         df: 99,
-                        skl: Skill::Death,
+                        sp: 30,
+                        ac: 100,
+                        ev: 10,
+                        cr: 10,
+                        skills: &[
+                            SkillChoice {
+                                skill: Skill::Death,
+                                weight: 10,
+                                condition: OncePerBattle,
+                            },
+                            SkillChoice {
+                                skill: Skill::Sword,
+                                weight: 4,
+                                condition: Always,
+                            },
+                            SkillChoice {
+                                skill: Skill::Spell(Item::SpellFire(2)),
+                                weight: 3,
+                                condition: EveryOtherTurn,
+                            },
+                            SkillChoice {
+                                skill: Skill::Spell(Item::SpellHeal(2)),
+                                weight: 6,
+                                condition: HpBelow(0.3),
+                            },
+                        ],
+                        tint: Color::WHITE,
+                        affinities: &[
+                            (Element::Fire, 0.5),
+                            (Element::Ice, 0.5),
+                            (Element::Dark, 0.),
+                            (Element::Holy, 1.5),
+                        ],
+                        phases: LICH_PHASES,
+                        gold: 0,
+                    },
+                ),
+            ])),
+            tables: HashMap::<_, _>::from_iter(IntoIter::new([
+                (
+                    Field::Grass,
+                    EncounterTable {
+                        rate: 20,
+                        groups: vec![
+                            vec![Goblin],
+                            vec![Goblin, Goblin],
+                            vec![Goblin, Goblin, Goblin],
+                            vec![Goblin, Goblin, Goblin, Goblin],
+                        ],
+                    },
+                ),
+                (
+                    Field::Forest,
+                    EncounterTable {
+                        rate: 10,
+                        groups: vec![
+                            vec![Skeleton],
+                            vec![Goblin, Skeleton, Goblin],
+                            vec![Skeleton, Skeleton],
+                            vec![Archer, Goblin],
+                        ],
+                    },
+                ),
+                (
+                    Field::Mountain,
+                    EncounterTable {
+                        rate: 5,
+                        groups: vec![vec![Griffin], vec![Griffin, Griffin]],
+                    },
+                ),
+                (
+                    Field::Floor,
+                    EncounterTable {
+                        rate: 8,
+                        groups: vec![
+                            vec![Skeleton, Skeleton],
+                            vec![Skeleton, Skeleton, Skeleton],
+                            vec![Goblin, Skeleton, Skeleton, Goblin],
+                            vec![Skeleton, Archer, Skeleton],
+                        ],
+                    },
+                ),
+                (
+                    Field::Throne,
+                    EncounterTable {
+                        rate: 1,
+                        groups: vec![vec![Boss]],
                     },
                 ),
             ])),
@@ -130,10 +425,10 @@ impl Default for EnemyData {
 }
 
 impl EnemyData {
-    pub fn create(&self, map_field: &Field, level: i32) -> CharacterStatus {
-        let &enemy_status = &self.data[map_field];
+    pub fn create(&self, enemy: &Enemy, level: i32) -> CharacterStatus {
+        let &enemy_status = &self.enemies[enemy];
         return CharacterStatus {
-            name: enemy_status.name.to_string(),
+            name: enemy.to_string(),
             lv: level,
             exp: 0,
             hp_current: (enemy_status.hp as f32 * (0.5 + level as f32 / 2.)) as i32,
@@ -142,20 +437,72 @@ impl EnemyData {
             mp_max: 0,
             attack: (enemy_status.at as f32 * (0.5 + level as f32 / 2.)) as i32,
             defence: (enemy_status.df as f32 * (0.5 + level as f32 / 2.)) as i32,
+            speed: (enemy_status.sp as f32 * (0.5 + level as f32 / 2.)) as i32,
+            accuracy: enemy_status.ac,
+            evasion: enemy_status.ev,
+            critical: enemy_status.cr,
+            defending: false,
+            ailments: Vec::new(),
         };
     }
-    pub fn field_to_enemy(&self, map_field: &Field) -> Enemy {
-        let &enemy_status = &self.data[map_field];
-        return enemy_status.name;
-    }
     pub fn field_to_rate(&self, map_field: &Field) -> i32 {
-        let &enemy_status = &self.data[map_field];
-        return enemy_status.rate;
+        return self.tables[map_field].rate;
+    }
+    // One of the groups of the field at random
+    pub fn field_to_group(&self, map_field: &Field) -> Vec<Enemy> {
+        let groups = &self.tables[map_field].groups;
+        let mut rng = rand::thread_rng();
+        return groups[rng.gen_range(0..groups.len())].clone();
     }
-    pub fn field_to_enemy_skill(&self, map_field: &Field) -> Skill {
-        let &enemy_status = &self.data[map_field];
-        return enemy_status.skl;
+    pub fn enemy_ai(&self, enemy: &Enemy) -> EnemyAi {
+        EnemyAi::new(self.enemies[enemy].skills)
     }
+    pub fn tint(&self, enemy: &Enemy) -> Color {
+        self.enemies[enemy].tint
+    }
+    pub fn affinities(&self, enemy: &Enemy) -> Affinities {
+        Affinities(self.enemies[enemy].affinities.to_vec())
+    }
+    // Gold dropped by an enemy of the level
+    pub fn gold(&self, enemy: &Enemy, level: i32) -> u32 {
+        self.enemies[enemy].gold * level.max(1) as u32
+    }
+    pub fn phases(&self, enemy: &Enemy) -> Phases {
+        Phases::new(self.enemies[enemy].phases)
+    }
+}
+
+// "Goblin", "Goblin and Skeleton", "Goblin, Goblin and Skeleton"
+pub fn group_text(group: &[Enemy]) -> String {
+    let names: Vec<String> = group.iter().map(|enemy| enemy.to_string()).collect();
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+// Enemies of the same kind are told apart by a letter, e.g. "Goblin A" and "Goblin B"
+fn enemy_names(group: &[Enemy]) -> Vec<String> {
+    let letters = ['A', 'B', 'C', 'D', 'E', 'F'];
+    let mut seen: HashMap<Enemy, usize> = HashMap::new();
+    group
+        .iter()
+        .map(|enemy| {
+            if group.iter().filter(|other| *other == enemy).count() == 1 {
+                return enemy.to_string();
+            }
+            let count = seen.entry(*enemy).or_insert(0);
+            *count += 1;
+            format!("{} {}", enemy, letters[(*count - 1) % letters.len()])
+        })
+        .collect()
+}
+
+// Horizontal center of an enemy in percent of the window width.
+// Enemies share the right two thirds of the screen, the left third shows the player's windows.
+pub fn enemy_screen_x(slot: usize, count: usize) -> f32 {
+    34. + 66. * (slot as f32 + 0.5) / count.max(1) as f32
 }
 
 // 敵のレベル設定
@@ -172,32 +519,38 @@ fn setup_battle(
     texture_assets: Res<TextureAssets>,
     mut battle: ResMut<Battle>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    player_camera_query: Query<(&MapCamera, &Transform, &Position)>,
+    player_camera_query: Query<(&MapCamera, &Transform)>,
     mut windows: ResMut<Windows>,
-    map: Res<Map>,
+    runstate: Res<RunState>,
     enemy_data: Res<EnemyData>,
     player_query: Query<&CharacterStatus, With<Player>>,
+    party_query: Query<(&CharacterStatus, &PartyMember)>,
+    mut player_state_query: Query<&mut Player>,
     mut audio_event_writer: EventWriter<AudioEvent>,
 ) {
-    let (_camera, player_transform, position) = player_camera_query.single().unwrap();
-    let map_field = map.position_to_field(position);
-    let enemy = enemy_data.field_to_enemy(&map_field);
+    let group = match &runstate.event {
+        Some(GameEvent::EnemyEncountered(group)) => group.clone(),
+        _ => panic!("battle started without an encounter"),
+    };
+    let (_camera, player_transform) = player_camera_query.single().unwrap();
     let player_status = player_query.single().unwrap();
-    let enemy_status = enemy_data.create(
-        &map_field,
-        level(player_status.lv, enemy_data.field_to_enemy(&map_field)),
-    );
-    let enemy_skill = enemy_data.field_to_enemy_skill(&map_field);
-    let enemy_sprite = texture_assets.get_handle_for_enemy(&enemy);
+    let names = enemy_names(&group);
 
     let window = windows.get_primary_mut().unwrap();
-    //TODO: orthographic_projection_scale should not be corrected. --> /.3
-    let enemy_window_size = Vec2::new(
-        window.width() as f32 * 2. / 3. / 3.,
-        window.height() / 3. as f32,
-    );
-    let enemy_root_offset = Vec2::new(enemy_window_size.x - window.width() as f32 / (2. * 3.), 0.);
-    let enemy_scale = 1.;
+    //TODO: orthographic_projection_scale should not be corrected. --> *.3
+    let window_width = window.width() as f32 * 0.3;
+    // 敵を横に並べる。多いときは縮小する
+    let enemy_scale = (window_width * 0.66 / group.len() as f32 / 120.).min(1.);
+    let enemy_offsets: Vec<Vec2> = (0..group.len())
+        .map(|slot| {
+            Vec2::new(
+                (enemy_screen_x(slot, group.len()) / 100. - 0.5) * window_width,
+                0.,
+            )
+        })
+        .collect();
+
+    let mut enemies = Vec::new();
     let battle_entity = commands
         .spawn()
         .insert(Transform::from_translation(Vec3::new(
@@ -228,54 +581,85 @@ fn setup_battle(
                     states: vec![AppState::InGameBattle],
                 });
             // 敵を追加
-            child_builder
-                .spawn_bundle(SpriteBundle {
-                    transform: Transform::from_translation(Vec3::new(
-                        enemy_root_offset.x,
-                        enemy_root_offset.y,
-                        0.,
-                    )),
-                    ..Default::default()
-                })
-                .insert(ForState {
-                    states: vec![AppState::InGameBattle],
-                })
-                .with_children(|child_builder| {
-                    child_builder
-                        .spawn_bundle(SpriteBundle {
-                            material: materials.add(enemy_sprite.clone().into()),
-                            transform: Transform {
-                                translation: Vec3::new(
-                                    0.,
-                                    0.,
-                                    render_layer(RenderLayer::BattleForeGround) as f32,
-                                ),
-                                scale: Vec3::new(enemy_scale, enemy_scale, 1.),
+            for (slot, enemy) in group.iter().enumerate() {
+                let mut enemy_status = enemy_data.create(enemy, level(player_status.lv, *enemy));
+                enemy_status.name = names[slot].clone();
+                let enemy_sprite = texture_assets.get_handle_for_enemy(enemy);
+                child_builder
+                    .spawn_bundle(SpriteBundle {
+                        transform: Transform::from_translation(Vec3::new(
+                            enemy_offsets[slot].x,
+                            enemy_offsets[slot].y,
+                            0.,
+                        )),
+                        ..Default::default()
+                    })
+                    .insert(ForState {
+                        states: vec![AppState::InGameBattle],
+                    })
+                    .with_children(|child_builder| {
+                        let entity = child_builder
+                            .spawn_bundle(SpriteBundle {
+                                material: materials.add(ColorMaterial::modulated_texture(
+                                    enemy_sprite,
+                                    enemy_data.tint(enemy),
+                                )),
+                                transform: Transform {
+                                    translation: Vec3::new(
+                                        0.,
+                                        0.,
+                                        render_layer(RenderLayer::BattleForeGround) as f32,
+                                    ),
+                                    scale: Vec3::new(enemy_scale, enemy_scale, 1.),
+                                    ..Default::default()
+                                },
                                 ..Default::default()
-                            },
-                            ..Default::default()
-                        })
-                        .insert(enemy_status.clone())
-                        .insert(enemy_skill)
-                        .insert(enemy)
-                        .insert(ForState {
-                            states: vec![AppState::InGameBattle],
-                        });
-                });
+                            })
+                            .insert(enemy_status)
+                            .insert(enemy_data.enemy_ai(enemy))
+                            .insert(enemy_data.affinities(enemy))
+                            .insert(enemy_data.phases(enemy))
+                            .insert(*enemy)
+                            .insert(EnemySlot(slot))
+                            .insert(ForState {
+                                states: vec![AppState::InGameBattle],
+                            })
+                            .id();
+                        enemies.push(entity);
+                    });
+            }
         })
         .id();
 
     battle.entity = Some(battle_entity);
-    battle.enemy_root_offset = Vec2::new(
-        player_transform.translation.x + enemy_root_offset.x,
-        player_transform.translation.y + enemy_root_offset.y,
-    );
-
-    if matches!(enemy, Enemy::Boss) {
-        audio_event_writer.send(AudioEvent::Play(AudioKind::BGMBattleLast));
-    } else {
-        audio_event_writer.send(AudioEvent::Play(AudioKind::BGMBattle));
+    battle.enemies = enemies;
+    battle.enemy_positions = enemy_offsets
+        .iter()
+        .map(|offset| {
+            Vec2::new(
+                player_transform.translation.x + offset.x,
+                player_transform.translation.y + offset.y,
+            )
+        })
+        .collect();
+    battle.enemy_scale = enemy_scale;
+    battle.target = 0;
+    // 倒れている仲間は戦えない
+    battle.actor = alive_members(party_query.iter())
+        .first()
+        .copied()
+        .unwrap_or(0);
+    battle.ally_target = battle.actor;
+    battle.can_run = !group.iter().any(|enemy| matches!(enemy, Enemy::Boss));
+    battle.turns.clear();
+    battle.dialogue.clear();
+    // 素早さで最初のラウンドの順番を決める
+    for mut player in player_state_query.iter_mut() {
+        player.battle_state = PlayerBattleState::NextTurn;
     }
+
+    // ボス戦の曲はフェーズが変わった時に切り替える
+    audio_event_writer.send(AudioEvent::Play(AudioKind::BGMBattle));
 }
 
 fn clean_up_battle(
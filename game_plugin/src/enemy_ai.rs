@@ -0,0 +1,185 @@
+use crate::character_status::{CharacterStatus, Skill};
+use rand::Rng;
+
+// When an enemy may pick a skill
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    Always,
+    // HP below this fraction of the max HP
+    HpBelow(f32),
+    // not on two turns in a row
+    EveryOtherTurn,
+    OncePerBattle,
+}
+
+// One entry of an enemy's skill list, picked by weight among the usable ones
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkillChoice {
+    pub skill: Skill,
+    pub weight: u32,
+    pub condition: Condition,
+}
+
+// The skill list of an enemy in battle and what it did so far
+#[derive(Clone, Debug, Default)]
+pub struct EnemyAi {
+    pub choices: Vec<SkillChoice>,
+    pub last: Option<Skill>,
+    pub used: Vec<Skill>,
+}
+
+impl EnemyAi {
+    pub fn new(choices: &[SkillChoice]) -> Self {
+        EnemyAi {
+            choices: choices.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn usable(&self, choice: &SkillChoice, status: &CharacterStatus) -> bool {
+        match choice.condition {
+            Condition::Always => true,
+            Condition::HpBelow(fraction) => {
+                (status.hp_current as f32) < status.hp_max as f32 * fraction
+            }
+            Condition::EveryOtherTurn => self.last != Some(choice.skill),
+            Condition::OncePerBattle => !self.used.contains(&choice.skill),
+        }
+    }
+
+    // Picks the skill of this turn and remembers it, Sword when nothing is usable
+    pub fn choose<R: Rng>(&mut self, status: &CharacterStatus, rng: &mut R) -> Skill {
+        let usable: Vec<SkillChoice> = self
+            .choices
+            .iter()
+            .filter(|choice| choice.weight > 0 && self.usable(choice, status))
+            .copied()
+            .collect();
+        let total: u32 = usable.iter().map(|choice| choice.weight).sum();
+        let picked = if total == 0 {
+            None
+        } else {
+            let mut roll = rng.gen_range(0..total);
+            usable.into_iter().find(|choice| {
+                if roll < choice.weight {
+                    return true;
+                }
+                roll -= choice.weight;
+                false
+            })
+        };
+        let skill = picked.map_or(Skill::Sword, |choice| choice.skill);
+        self.last = Some(skill);
+        // only once-per-battle skills need remembering
+        if let Some(SkillChoice {
+            condition: Condition::OncePerBattle,
+            ..
+        }) = picked
+        {
+            self.used.push(skill);
+        }
+        skill
+    }
+
+    // The first skill of the list, e.g. for a free hit on a fleeing hero
+    pub fn basic_skill(&self) -> Skill {
+        self.choices
+            .iter()
+            .find(|choice| choice.condition == Condition::Always && choice.skill != Skill::Defend)
+            .map(|choice| choice.skill)
+            .unwrap_or(Skill::Sword)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::Item;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn choice(skill: Skill, weight: u32, condition: Condition) -> SkillChoice {
+        SkillChoice {
+            skill,
+            weight,
+            condition,
+        }
+    }
+
+    fn status(hp_current: i32) -> CharacterStatus {
+        CharacterStatus {
+            hp_current,
+            hp_max: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn heals_only_when_hurt() {
+        let heal = Skill::Spell(Item::SpellHeal(1));
+        let mut ai = EnemyAi::new(&[
+            choice(Skill::Sword, 1, Condition::Always),
+            choice(heal, 1000, Condition::HpBelow(0.3)),
+        ]);
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            assert_eq!(ai.choose(&status(50), &mut rng), Skill::Sword);
+        }
+        assert_eq!(ai.choose(&status(29), &mut rng), heal);
+    }
+
+    #[test]
+    fn every_other_turn_never_repeats() {
+        let mut ai = EnemyAi::new(&[
+            choice(Skill::Sword, 1, Condition::Always),
+            choice(Skill::Wind, 1000, Condition::EveryOtherTurn),
+        ]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut previous = None;
+        for _ in 0..20 {
+            let skill = ai.choose(&status(100), &mut rng);
+            if skill == Skill::Wind {
+                assert_ne!(previous, Some(Skill::Wind));
+            }
+            previous = Some(skill);
+        }
+    }
+
+    #[test]
+    fn once_per_battle_is_used_once() {
+        let mut ai = EnemyAi::new(&[
+            choice(Skill::Sword, 1, Condition::Always),
+            choice(Skill::Death, 1000, Condition::OncePerBattle),
+        ]);
+        let mut rng = StdRng::seed_from_u64(2);
+        let deaths = (0..30)
+            .filter(|_| ai.choose(&status(100), &mut rng) == Skill::Death)
+            .count();
+        assert_eq!(deaths, 1);
+        assert_eq!(ai.used, vec![Skill::Death]);
+    }
+
+    #[test]
+    fn weights_decide_how_often() {
+        let mut ai = EnemyAi::new(&[
+            choice(Skill::Sword, 9, Condition::Always),
+            choice(Skill::Defend, 1, Condition::Always),
+        ]);
+        let mut rng = StdRng::seed_from_u64(3);
+        let swords = (0..1000)
+            .filter(|_| ai.choose(&status(100), &mut rng) == Skill::Sword)
+            .count();
+        assert!((850..950).contains(&swords), "{}", swords);
+    }
+
+    #[test]
+    fn nothing_usable_falls_back_to_sword() {
+        let mut ai = EnemyAi::new(&[choice(
+            Skill::Spell(Item::SpellHeal(1)),
+            1,
+            Condition::HpBelow(0.3),
+        )]);
+        let mut rng = StdRng::seed_from_u64(4);
+        assert_eq!(ai.choose(&status(100), &mut rng), Skill::Sword);
+    }
+}
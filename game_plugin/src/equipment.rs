@@ -0,0 +1,359 @@
+use crate::character_status::CharacterStatus;
+use crate::explore_actions::FieldMenu;
+use crate::growth::Growth;
+use crate::inventory::{Inventory, Item};
+use crate::party::PartyMember;
+use crate::AppState;
+use bevy::prelude::*;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+pub struct EquipmentPlugin;
+
+// This plugin lets the player change the gear of the party on the map
+impl Plugin for EquipmentPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<EquipMenu>().add_system_set(
+            SystemSet::on_update(AppState::InGameExplore).with_system(equip_menu.system()),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Slot {
+    Weapon,
+    Shield,
+    Head,
+    Body,
+    Arms,
+    Legs,
+    Accessory,
+}
+
+// In the order of the equip screen
+pub const SLOTS: [Slot; 7] = [
+    Slot::Weapon,
+    Slot::Shield,
+    Slot::Head,
+    Slot::Body,
+    Slot::Arms,
+    Slot::Legs,
+    Slot::Accessory,
+];
+
+impl Display for Slot {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, fmt)
+    }
+}
+
+// What a piece of gear adds to the stats of the level
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bonus {
+    pub hp: i32,
+    pub mp: i32,
+    pub attack: i32,
+    pub defence: i32,
+    pub speed: i32,
+    pub critical: i32,
+}
+
+impl Bonus {
+    pub fn plus(self, other: Bonus) -> Bonus {
+        Bonus {
+            hp: self.hp + other.hp,
+            mp: self.mp + other.mp,
+            attack: self.attack + other.attack,
+            defence: self.defence + other.defence,
+            speed: self.speed + other.speed,
+            critical: self.critical + other.critical,
+        }
+    }
+}
+
+// The gear a hero wears, one piece per slot. The pieces stay in the inventory.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Equipment {
+    pub weapon: Option<Item>,
+    pub shield: Option<Item>,
+    pub head: Option<Item>,
+    pub body: Option<Item>,
+    pub arms: Option<Item>,
+    pub legs: Option<Item>,
+    pub accessory: Option<Item>,
+}
+
+impl Equipment {
+    pub fn get(&self, slot: Slot) -> Option<Item> {
+        match slot {
+            Slot::Weapon => self.weapon,
+            Slot::Shield => self.shield,
+            Slot::Head => self.head,
+            Slot::Body => self.body,
+            Slot::Arms => self.arms,
+            Slot::Legs => self.legs,
+            Slot::Accessory => self.accessory,
+        }
+    }
+    fn slot_mut(&mut self, slot: Slot) -> &mut Option<Item> {
+        match slot {
+            Slot::Weapon => &mut self.weapon,
+            Slot::Shield => &mut self.shield,
+            Slot::Head => &mut self.head,
+            Slot::Body => &mut self.body,
+            Slot::Arms => &mut self.arms,
+            Slot::Legs => &mut self.legs,
+            Slot::Accessory => &mut self.accessory,
+        }
+    }
+    // Puts the item on and returns what was worn in its slot
+    pub fn equip(&mut self, item: Item) -> Option<Item> {
+        let slot = item.slot()?;
+        self.slot_mut(slot).replace(item)
+    }
+    pub fn unequip(&mut self, slot: Slot) -> Option<Item> {
+        self.slot_mut(slot).take()
+    }
+    pub fn is_equipped(&self, item: &Item) -> bool {
+        SLOTS.iter().any(|slot| self.get(*slot) == Some(*item))
+    }
+    pub fn bonus(&self) -> Bonus {
+        SLOTS
+            .iter()
+            .filter_map(|slot| self.get(*slot))
+            .fold(Bonus::default(), |bonus, item| bonus.plus(item.bonus()))
+    }
+}
+
+// Gear in the inventory that fits the slot
+pub fn candidates(inventory: &Inventory, slot: Slot) -> Vec<Item> {
+    inventory
+        .items
+        .iter()
+        .filter(|item| item.slot() == Some(slot))
+        .copied()
+        .collect()
+}
+
+// The hero's stats as they would be with the item in the slot, None takes it off
+pub fn preview(
+    status: &CharacterStatus,
+    growth: &Growth,
+    equipment: &Equipment,
+    slot: Slot,
+    item: Option<Item>,
+) -> CharacterStatus {
+    let mut equipment = equipment.clone();
+    match item {
+        Some(item) => equipment.equip(item),
+        None => equipment.unequip(slot),
+    };
+    let mut status = status.clone();
+    status.update_stats(growth, &equipment);
+    status
+}
+
+// (name, now, after) of the stats that would change
+pub fn deltas(before: &CharacterStatus, after: &CharacterStatus) -> Vec<(&'static str, i32, i32)> {
+    vec![
+        ("HP", before.hp_max, after.hp_max),
+        ("MP", before.mp_max, after.mp_max),
+        ("AT", before.attack, after.attack),
+        ("DF", before.defence, after.defence),
+        ("SP", before.speed, after.speed),
+        ("CR", before.critical, after.critical),
+    ]
+    .into_iter()
+    .filter(|(_, now, after)| now != after)
+    .collect()
+}
+
+// The equip screen of the map, opened with E
+#[derive(Default)]
+pub struct EquipMenu {
+    pub open: bool,
+    // party order of the hero being dressed
+    pub member: usize,
+    // index into SLOTS, the one after the last is "Close"
+    pub cursor: usize,
+    // slot whose gear is being chosen, and the cursor in its list.
+    // The one after the last candidate is "Remove".
+    pub choosing: Option<Slot>,
+    pub pick: usize,
+    // what happened, e.g. "Knight equipped the IronSword."
+    pub message: String,
+}
+
+fn equip_menu(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut menu: ResMut<EquipMenu>,
+    field_menu: Res<FieldMenu>,
+    growth: Res<Growth>,
+    mut party_query: Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
+) {
+    if !menu.open {
+        if !field_menu.open && keyboard_input.just_pressed(KeyCode::E) {
+            keyboard_input.reset(KeyCode::E);
+            *menu = EquipMenu {
+                open: true,
+                ..Default::default()
+            };
+        }
+        return;
+    }
+    let party_size = party_query.iter_mut().count();
+    let member = menu.member;
+    let (mut status, mut inventory) =
+        match party_query.iter_mut().find(|(_, _, m)| m.order == member) {
+            Some((status, inventory, _)) => (status, inventory),
+            None => {
+                menu.member = 0;
+                return;
+            }
+        };
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        if menu.choosing.take().is_none() {
+            menu.open = false;
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Up) || keyboard_input.just_pressed(KeyCode::W) {
+        if menu.choosing.is_some() {
+            menu.pick = menu.pick.saturating_sub(1);
+        } else {
+            menu.cursor = menu.cursor.saturating_sub(1);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Down) || keyboard_input.just_pressed(KeyCode::S)
+    {
+        match menu.choosing {
+            Some(slot) => menu.pick = (menu.pick + 1).min(candidates(&inventory, slot).len()),
+            None => menu.cursor = (menu.cursor + 1).min(SLOTS.len()),
+        }
+    } else if menu.choosing.is_none()
+        && (keyboard_input.just_pressed(KeyCode::Left) || keyboard_input.just_pressed(KeyCode::A))
+    {
+        // 左右で装備を変える仲間を選ぶ
+        menu.member = menu.member.saturating_sub(1);
+        menu.message = String::new();
+    } else if menu.choosing.is_none()
+        && (keyboard_input.just_pressed(KeyCode::Right) || keyboard_input.just_pressed(KeyCode::D))
+    {
+        menu.member = (menu.member + 1).min(party_size - 1);
+        menu.message = String::new();
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        keyboard_input.reset(KeyCode::Return);
+        match menu.choosing.take() {
+            None => match SLOTS.get(menu.cursor) {
+                Some(slot) => {
+                    let equipped = inventory.equipment.get(*slot);
+                    menu.pick = candidates(&inventory, *slot)
+                        .iter()
+                        .position(|item| Some(*item) == equipped)
+                        .unwrap_or(0);
+                    menu.choosing = Some(*slot);
+                }
+                None => menu.open = false,
+            },
+            Some(slot) => {
+                menu.message = match candidates(&inventory, slot).get(menu.pick) {
+                    Some(item) => {
+                        inventory.equipment.equip(*item);
+                        format!("{} equipped the {}.", status.name, item)
+                    }
+                    None => match inventory.equipment.unequip(slot) {
+                        Some(item) => format!("{} took off the {}.", status.name, item),
+                        None => String::new(),
+                    },
+                };
+                status.update_stats(&growth, &inventory.equipment);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equipping_replaces_the_piece_in_the_slot() {
+        let mut equipment = Equipment::default();
+        assert_eq!(equipment.equip(Item::CopperSword), None);
+        assert_eq!(equipment.equip(Item::HeroSword), Some(Item::CopperSword));
+        assert_eq!(equipment.get(Slot::Weapon), Some(Item::HeroSword));
+        assert!(!equipment.is_equipped(&Item::CopperSword));
+        assert_eq!(equipment.unequip(Slot::Weapon), Some(Item::HeroSword));
+        assert_eq!(equipment, Equipment::default());
+    }
+
+    #[test]
+    fn spells_and_consumables_are_not_worn() {
+        let mut equipment = Equipment::default();
+        assert_eq!(equipment.equip(Item::SpellFire(1)), None);
+        assert_eq!(equipment.equip(Item::Herb), None);
+        assert_eq!(equipment, Equipment::default());
+    }
+
+    #[test]
+    fn stats_are_the_level_plus_the_gear() {
+        let growth = Growth::default();
+        let mut status = CharacterStatus::default();
+        let mut equipment = Equipment::default();
+        status.update_stats(&growth, &equipment);
+        let bare = status.clone();
+        equipment.equip(Item::HeroSword);
+        equipment.equip(Item::IronBody);
+        status.update_stats(&growth, &equipment);
+        assert_eq!(status.attack, bare.attack + Item::HeroSword.bonus().attack);
+        assert_eq!(status.critical, bare.critical + 20);
+        assert_eq!(status.hp_max, bare.hp_max + Item::IronBody.bonus().hp);
+        equipment.unequip(Slot::Weapon);
+        status.update_stats(&growth, &equipment);
+        assert_eq!(status.attack, bare.attack);
+    }
+
+    #[test]
+    fn taking_armour_off_lowers_current_hp() {
+        let growth = Growth::default();
+        let mut status = CharacterStatus::default();
+        let mut equipment = Equipment::default();
+        equipment.equip(Item::IronBody);
+        status.update_stats(&growth, &equipment);
+        status.heal2max();
+        equipment.unequip(Slot::Body);
+        status.update_stats(&growth, &equipment);
+        assert_eq!(status.hp_current, status.hp_max);
+    }
+
+    #[test]
+    fn preview_shows_only_what_changes() {
+        let mut inventory = Inventory::default();
+        inventory.add_item(Item::CopperSword);
+        inventory.add_item(Item::IronSword);
+        assert_eq!(
+            candidates(&inventory, Slot::Weapon),
+            vec![Item::CopperSword, Item::IronSword]
+        );
+        let growth = Growth::default();
+        let mut status = CharacterStatus::default();
+        status.update_stats(&growth, &inventory.equipment);
+        let after = preview(
+            &status,
+            &growth,
+            &inventory.equipment,
+            Slot::Weapon,
+            Some(Item::IronSword),
+        );
+        let gain = Item::IronSword.bonus().attack - Item::CopperSword.bonus().attack;
+        assert_eq!(
+            deltas(&status, &after),
+            vec![("AT", status.attack, status.attack + gain)]
+        );
+        let after = preview(&status, &growth, &inventory.equipment, Slot::Weapon, None);
+        assert_eq!(
+            deltas(&status, &after)[0].2,
+            status.attack - Item::CopperSword.bonus().attack
+        );
+    }
+}
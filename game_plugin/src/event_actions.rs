@@ -1,5 +1,11 @@
-use crate::actions::PlayerActions;
+use crate::actions::{Action, PlayerActions};
+use crate::character_status::{Ailment, CharacterStatus};
+use crate::dungeon::{Dungeon, DungeonTransition};
 use crate::events::{GameEvent, RunState};
+use crate::growth::Growth;
+use crate::inventory::Inventory;
+use crate::party::PartyMember;
+use crate::shop::{ShopData, TownMenu, TownScreen, INN_PRICE};
 use crate::AppState;
 use bevy::prelude::*;
 
@@ -21,20 +27,45 @@ fn update_events(
     runstate: Res<RunState>,
     mut state: ResMut<State<AppState>>,
     mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut dungeon: ResMut<Dungeon>,
+    mut town_menu: ResMut<TownMenu>,
+    shop_data: Res<ShopData>,
+    growth: Res<Growth>,
+    mut party_query: Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
 ) {
-    if matches!(actions.action, None) {
-        return;
-    }
+    let action = match actions.action {
+        Some(action) => action,
+        None => return,
+    };
 
     let event = runstate.event.as_ref().unwrap();
+    // 町ではメニューを選ぶ、それ以外は決定キーで閉じる
+    if !matches!(event, GameEvent::TownArrived(_, _)) && !matches!(action, Action::Return) {
+        return;
+    }
     match event {
-        GameEvent::EnemyEncountered(_enemy) => {
+        GameEvent::EnemyEncountered(_) => {
             state.set(AppState::InGameBattle).unwrap();
         }
         GameEvent::TownArrived(_, _) => {
+            if town_action(
+                action,
+                &mut town_menu,
+                &shop_data,
+                &growth,
+                &mut party_query,
+            ) {
+                state.set(AppState::InGameExplore).unwrap();
+            }
+        }
+        GameEvent::DungeonEntered => {
+            dungeon.transition = Some(DungeonTransition::Enter);
+            state.set(AppState::InGameMap).unwrap();
+        }
+        GameEvent::FloorChanged(_) => {
             state.set(AppState::InGameExplore).unwrap();
         }
-        GameEvent::Win(_levelup) => {
+        GameEvent::Win(..) => {
             state.set(AppState::InGameExplore).unwrap();
         }
         // TODO: Return map with experience
@@ -48,3 +79,105 @@ fn update_events(
     }
     actions.reset_all(&mut keyboard_input);
 }
+
+// Moves through the town menu, true when the player leaves the town
+fn town_action(
+    action: Action,
+    town_menu: &mut TownMenu,
+    shop_data: &ShopData,
+    growth: &Growth,
+    party_query: &mut Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
+) -> bool {
+    if matches!(action, Action::Return)
+        && town_menu.screen == TownScreen::Menu
+        && town_menu.cursor == 0
+    {
+        stay_at_inn(town_menu, party_query);
+        return false;
+    }
+    // お金と買い物は主人公の持ち物で行う
+    let (mut status, mut inventory) = match party_query
+        .iter_mut()
+        .find(|(_, _, member)| member.order == 0)
+    {
+        Some((status, inventory, _)) => (status, inventory),
+        None => return true,
+    };
+    match action {
+        Action::Up => town_menu.up(),
+        Action::Down => {
+            let len = town_menu.len(shop_data, &inventory);
+            town_menu.down(len);
+        }
+        Action::Left | Action::Right => {}
+        Action::Return => {
+            let cursor = town_menu.cursor;
+            town_menu.message = String::new();
+            match town_menu.screen {
+                TownScreen::Menu if cursor == 1 => town_menu.open(TownScreen::Buy),
+                TownScreen::Menu => return true,
+                TownScreen::Buy => match shop_data.goods.get(cursor) {
+                    Some(item) => {
+                        let price = shop_data.price(item).unwrap_or(0);
+                        town_menu.message = match inventory.buy(*item, price) {
+                            Ok(()) => format!("You bought a {}.", item),
+                            Err(message) => message.to_string(),
+                        };
+                    }
+                    None if cursor == shop_data.goods.len() => town_menu.open(TownScreen::Sell),
+                    None => {
+                        town_menu.open(TownScreen::Menu);
+                        town_menu.cursor = 1;
+                    }
+                },
+                TownScreen::Sell => match shop_data.sellable(&inventory).get(cursor) {
+                    Some(item) => {
+                        let price = shop_data.sell_price(item).unwrap_or(0);
+                        inventory.sell(*item, price);
+                        town_menu.message = format!("You sold a {} for {} G.", item, price);
+                        // 売った分だけ一覧が短くなる
+                        let len = town_menu.len(shop_data, &inventory);
+                        town_menu.cursor = cursor.min(len - 1);
+                    }
+                    None => town_menu.open(TownScreen::Buy),
+                },
+            }
+            // 装備が変わればステータスも変わる
+            status.update_stats(growth, &inventory.equipment);
+        }
+    }
+    false
+}
+
+// Heals and revives every hero for a few gold each
+fn stay_at_inn(
+    town_menu: &mut TownMenu,
+    party_query: &mut Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
+) {
+    let price = INN_PRICE * party_query.iter_mut().count() as u32;
+    let paid = match party_query
+        .iter_mut()
+        .find(|(_, _, member)| member.order == 0)
+    {
+        Some((_, mut inventory, _)) if inventory.gold >= price => {
+            inventory.gold -= price;
+            true
+        }
+        _ => false,
+    };
+    if !paid {
+        town_menu.message = "Not enough gold.".to_string();
+        return;
+    }
+    // 倒れた仲間も宿屋で生き返る
+    for (mut status, ..) in party_query.iter_mut() {
+        status.heal2max();
+        status.cure(&[
+            Ailment::Poison,
+            Ailment::Sleep,
+            Ailment::Paralysis,
+            Ailment::Silence,
+        ]);
+    }
+    town_menu.message = "You had a good night's rest.".to_string();
+}
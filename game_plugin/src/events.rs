@@ -1,13 +1,20 @@
 use bevy::prelude::*;
 use rand::Rng;
 
-use crate::character_status::{CharacterStatus, Skill};
-use crate::effects::{skill_to_effect, EffectEvent};
-use crate::enemies::Enemy;
+use crate::character_status::{Ailment, CharacterStatus, LevelUp, Skill, SkillTarget};
+use crate::dungeon::{Dungeon, DungeonTransition};
+use crate::effects::{skill_to_effect, EffectEvent, EffectKind, Hit};
+use crate::element::{Affinities, Element, ElementData};
+use crate::enemies::{Battle, Enemy, EnemyData, EnemySlot};
+use crate::enemy_ai::EnemyAi;
+use crate::growth::Growth;
 use crate::inventory::{Inventory, Item};
 use crate::map::{Map, Position};
+use crate::party::{spawn_member, PartyMember, RecruitData, PARTY_SIZE};
 use crate::player::{Player, PlayerBattleState};
 use crate::setup::MapCamera;
+use crate::shop::TownMenu;
+use crate::turn_order::{turn_order, Combatant};
 use crate::AppState;
 
 pub struct EventsPlugin;
@@ -28,9 +35,12 @@ impl Plugin for EventsPlugin {
 
 #[derive(Debug)]
 pub enum GameEvent {
-    EnemyEncountered(Enemy),
+    EnemyEncountered(Vec<Enemy>),
     TownArrived(Item, bool),
-    Win(bool),
+    DungeonEntered,
+    FloorChanged(DungeonTransition),
+    // level ups of the party and the gold dropped
+    Win(Vec<LevelUp>, u32),
     Lose,
     WinLast,
 }
@@ -45,29 +55,63 @@ fn explore_events(
     mut map: ResMut<Map>,
     mut state: ResMut<State<AppState>>,
     position_query: Query<&Position, With<MapCamera>>,
-    mut player_status_query: Query<(&mut CharacterStatus, &mut Inventory), With<Player>>,
+    mut commands: Commands,
+    mut party_query: Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
+    recruit_data: Res<RecruitData>,
     mut runstate: ResMut<RunState>,
+    mut dungeon: ResMut<Dungeon>,
+    mut town_menu: ResMut<TownMenu>,
+    growth: Res<Growth>,
 ) {
     for event in events_reader.iter() {
         match event {
-            GameEvent::EnemyEncountered(enemy) => {
-                runstate.event = Option::from(GameEvent::EnemyEncountered(*enemy));
+            GameEvent::EnemyEncountered(group) => {
+                runstate.event = Option::from(GameEvent::EnemyEncountered(group.clone()));
                 state.set(AppState::InGameEvent).unwrap();
             }
             GameEvent::TownArrived(item, visited) => {
                 let position = position_query.single().unwrap();
-                for (mut player_status, mut inventory) in player_status_query.iter_mut() {
-                    if !visited {
-                        inventory.add_item(item.clone());
-                        map.got_item((position.x as i32, position.y as i32));
-                        let current_lv = player_status.lv;
-                        player_status.level_up(current_lv, &inventory);
+                let party_size = party_query.iter_mut().count();
+                // 仲間が待つ町では宝は仲間が持ってくる
+                let recruit = recruit_data
+                    .recruit(item)
+                    .filter(|_| !visited && party_size < PARTY_SIZE);
+                let mut leader = None;
+                for (mut player_status, mut inventory, member) in party_query.iter_mut() {
+                    if member.order == 0 {
+                        if !visited && recruit.is_none() {
+                            inventory.add_item(item.clone());
+                            player_status.update_stats(&growth, &inventory.equipment);
+                        }
+                        leader = Some(player_status.clone());
                     }
-                    player_status.heal2max();
-
-                    runstate.event = Option::from(GameEvent::TownArrived(item.clone(), *visited));
-                    state.set(AppState::InGameEvent).unwrap();
                 }
+                if let (Some(recruit), Some(leader)) = (recruit, leader) {
+                    spawn_member(
+                        &mut commands,
+                        party_size,
+                        recruit.join(&leader, *item, &growth),
+                    );
+                }
+                if !visited {
+                    map.got_item((position.x as i32, position.y as i32));
+                }
+                *town_menu = TownMenu::default();
+                map.last_town = Some((position.x as i32, position.y as i32));
+
+                runstate.event = Option::from(GameEvent::TownArrived(item.clone(), *visited));
+                state.set(AppState::InGameEvent).unwrap();
+            }
+            GameEvent::DungeonEntered => {
+                let position = position_query.single().unwrap();
+                dungeon.world_position = (position.x as i32, position.y as i32);
+                runstate.event = Option::from(GameEvent::DungeonEntered);
+                state.set(AppState::InGameEvent).unwrap();
+            }
+            GameEvent::FloorChanged(transition) => {
+                // the map plugin swaps floors when the map scene starts again
+                dungeon.transition = Some(*transition);
+                state.set(AppState::InGameMap).unwrap();
             }
             _ => {
                 panic!("unhandled event!!")
@@ -78,79 +122,482 @@ fn explore_events(
 
 fn battle_events(
     mut state: ResMut<State<AppState>>,
-    mut player_status_query: Query<
-        (&mut CharacterStatus, &Inventory, &mut Player),
-        Changed<Player>,
+    mut player_query: Query<&mut Player, Changed<Player>>,
+    mut party_query: Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
+    mut enemy_status_query: Query<
+        (
+            &mut CharacterStatus,
+            &mut EnemyAi,
+            &Enemy,
+            &EnemySlot,
+            &mut Visible,
+            &Affinities,
+        ),
+        Without<PartyMember>,
     >,
-    mut enemy_status_query: Query<(&mut CharacterStatus, &Skill, &Enemy), Without<Player>>,
     mut effect_events: EventWriter<EffectEvent>,
     mut runstate: ResMut<RunState>,
+    mut battle: ResMut<Battle>,
+    element_data: Res<ElementData>,
+    enemy_data: Res<EnemyData>,
+    growth: Res<Growth>,
 ) {
-    for (mut player_status, inventory, mut player) in player_status_query.iter_mut() {
-        for (mut enemy_status, skill, enemy) in enemy_status_query.iter_mut() {
-            match player.battle_state {
-                PlayerBattleState::Attack => {
-                    let dmg_or_heal =
-                        attack(&mut player_status, &mut enemy_status, inventory.skill());
-                    effect_events.send(EffectEvent {
-                        kind: skill_to_effect(inventory.skill()),
-                        damage_or_heal: dmg_or_heal,
-                        is_player_attack: true,
+    for mut player in player_query.iter_mut() {
+        match player.battle_state {
+            PlayerBattleState::Attack => {
+                let actor = battle.actor;
+                // 道具は主人公の持ち物を皆で使う
+                let stash = party_query
+                    .iter_mut()
+                    .find(|(_, _, member)| member.order == 0)
+                    .map_or(Vec::new(), |(_, inventory, _)| {
+                        inventory.consumables.clone()
                     });
+                let (mut actor_status, skill) = match party_query
+                    .iter_mut()
+                    .find(|(_, _, member)| member.order == actor)
+                {
+                    Some((actor_status, inventory, _)) => (actor_status, inventory.skill(&stash)),
+                    None => continue,
+                };
+                let enough_mp = pay_mp(&mut actor_status, skill);
+                match skill.target() {
+                    SkillTarget::Myself if skill == Skill::Defend => {
+                        let mp = defend(&mut actor_status);
+                        effect_events.send(EffectEvent {
+                            kind: EffectKind::Defend,
+                            damage_or_heal: mp,
+                            is_player_attack: true,
+                            target: None,
+                            hit: Hit::Normal,
+                        });
+                    }
+                    SkillTarget::Myself if skill == Skill::Run => {
+                        let mut rng = rand::thread_rng();
+                        let living: Vec<(usize, CharacterStatus)> = enemy_status_query
+                            .iter_mut()
+                            .filter(|(enemy_status, ..)| enemy_status.hp_current > 0)
+                            .map(|(enemy_status, _, _, slot, ..)| (slot.0, enemy_status.clone()))
+                            .collect();
+                        let chaser = match run_away(&actor_status, &living, &mut rng) {
+                            Some(chaser) => chaser,
+                            None => {
+                                for (mut member_status, ..) in party_query.iter_mut() {
+                                    member_status.end_battle();
+                                }
+                                state.set(AppState::InGameExplore).unwrap();
+                                player.battle_state = PlayerBattleState::Select;
+                                continue;
+                            }
+                        };
+                        for (mut enemy_status, ai, _, slot, ..) in enemy_status_query.iter_mut() {
+                            if slot.0 != chaser {
+                                continue;
+                            }
+                            let enemy_skill = &ai.basic_skill();
+                            let (dmg, hit) = attack(
+                                &mut enemy_status,
+                                &mut actor_status,
+                                *enemy_skill,
+                                element_data.element(enemy_skill),
+                                &Affinities::default(),
+                                &mut rng,
+                            );
+                            effect_events.send(EffectEvent {
+                                kind: skill_to_effect(*enemy_skill),
+                                damage_or_heal: dmg,
+                                is_player_attack: false,
+                                target: Some(slot.0),
+                                hit,
+                            });
+                        }
+                    }
+                    // Skill::target only gives Defend and Run to the user itself
+                    SkillTarget::Myself => unreachable!("{} has no battle action", skill),
+                    SkillTarget::Ally => {
+                        let kind = skill_to_effect(skill);
+                        let (_, _, heal, _) = skill2param(&actor_status, &actor_status, skill);
+                        let mut heal = if enough_mp { heal } else { 0 };
+                        let ally_target = battle.ally_target;
+                        // 倒れた仲間も回復魔法で生き返る
+                        let mut used = false;
+                        for (mut ally_status, _, member) in party_query.iter_mut() {
+                            if member.order != ally_target {
+                                continue;
+                            }
+                            match (skill, enough_mp) {
+                                (Skill::Item(item), _) => {
+                                    let (hp, mp, cured) = ally_status.use_item(&item);
+                                    heal = hp.max(mp);
+                                    used = heal > 0 || cured;
+                                }
+                                (Skill::Spell(item), true) => {
+                                    ally_status.hp_current = (ally_status.hp_current + heal)
+                                        .clamp(0, ally_status.hp_max);
+                                    ally_status.cure(item.cures());
+                                }
+                                _ => {}
+                            }
+                        }
+                        // 道具は使うとなくなる。効き目がなければ残る
+                        if let (Skill::Item(item), true) = (skill, used) {
+                            for (_, mut inventory, member) in party_query.iter_mut() {
+                                if member.order == 0 {
+                                    inventory.consume(&item);
+                                }
+                            }
+                        }
+                        effect_events.send(EffectEvent {
+                            kind,
+                            damage_or_heal: heal,
+                            is_player_attack: true,
+                            target: None,
+                            hit: Hit::Normal,
+                        });
+                    }
+                    target => {
+                        let kind = skill_to_effect(skill);
+                        let element = element_data.element(&skill);
+                        let mut rng = rand::thread_rng();
+                        for (mut enemy_status, _, _, slot, _, affinities) in
+                            enemy_status_query.iter_mut()
+                        {
+                            if enemy_status.hp_current <= 0
+                                || (target == SkillTarget::Enemy && slot.0 != battle.target)
+                            {
+                                continue;
+                            }
+                            let (dmg, hit) = if enough_mp {
+                                attack(
+                                    &mut actor_status,
+                                    &mut enemy_status,
+                                    skill,
+                                    element,
+                                    affinities,
+                                    &mut rng,
+                                )
+                            } else {
+                                (0, Hit::Normal)
+                            };
+                            effect_events.send(EffectEvent {
+                                kind: kind.clone(),
+                                damage_or_heal: dmg,
+                                is_player_attack: true,
+                                target: Some(slot.0),
+                                hit,
+                            });
+                        }
+                    }
                 }
-                PlayerBattleState::Defense => {
+            }
+            PlayerBattleState::NextTurn => {
+                for (enemy_status, _, _, _, mut visible, _) in enemy_status_query.iter_mut() {
                     if enemy_status.hp_current <= 0 {
-                        if matches!(enemy, Enemy::Boss) {
-                            runstate.event = Option::from(GameEvent::WinLast);
-                            state.set(AppState::InGameEvent).unwrap();
-                        } else {
-                            let levelup =
-                                player_status.add_exp(enemy_status.hp_max / 10, &inventory);
-                            runstate.event = Option::from(GameEvent::Win(levelup));
-                            state.set(AppState::InGameEvent).unwrap();
+                        visible.is_visible = false;
+                    }
+                }
+                if enemy_status_query
+                    .iter_mut()
+                    .all(|(enemy_status, ..)| enemy_status.hp_current <= 0)
+                {
+                    if enemy_status_query
+                        .iter_mut()
+                        .any(|(_, _, enemy, ..)| matches!(enemy, Enemy::Boss))
+                    {
+                        runstate.event = Option::from(GameEvent::WinLast);
+                    } else {
+                        let exp = enemy_status_query
+                            .iter_mut()
+                            .map(|(enemy_status, ..)| enemy_status.hp_max / 10)
+                            .sum();
+                        let gold = enemy_status_query
+                            .iter_mut()
+                            .map(|(enemy_status, _, enemy, ..)| {
+                                enemy_data.gold(enemy, enemy_status.lv)
+                            })
+                            .sum();
+                        // 倒れている仲間は経験値をもらえない
+                        let mut levelups = Vec::new();
+                        for (mut member_status, mut inventory, member) in party_query.iter_mut() {
+                            member_status.end_battle();
+                            if member_status.hp_current > 0 {
+                                levelups.extend(member_status.add_exp(
+                                    exp,
+                                    &growth,
+                                    &mut inventory,
+                                ));
+                            }
+                            // お金は主人公がまとめて持つ
+                            if member.order == 0 {
+                                inventory.gold += gold;
+                            }
                         }
-                        player.battle_state = PlayerBattleState::Select
+                        runstate.event = Option::from(GameEvent::Win(levelups, gold));
                     }
-                    let dmg = attack(&mut enemy_status, &mut player_status, *skill);
-                    effect_events.send(EffectEvent {
-                        kind: skill_to_effect(*skill),
-                        damage_or_heal: dmg,
-                        is_player_attack: false,
-                    });
+                    state.set(AppState::InGameEvent).unwrap();
+                    player.battle_state = PlayerBattleState::Select;
+                    continue;
+                }
+                let alive: Vec<usize> = {
+                    let mut alive: Vec<usize> = party_query
+                        .iter_mut()
+                        .filter(|(member_status, ..)| member_status.hp_current > 0)
+                        .map(|(_, _, member)| member.order)
+                        .collect();
+                    alive.sort_unstable();
+                    alive
+                };
+                if alive.is_empty() {
+                    runstate.event = Option::from(GameEvent::Lose);
+                    state.set(AppState::InGameEvent).unwrap();
+                    player.battle_state = PlayerBattleState::Select;
+                    continue;
+                }
+                // ボスの台詞を読み終えるまで待つ
+                if !battle.dialogue.is_empty() {
+                    continue;
                 }
-                PlayerBattleState::Select => {
-                    if player_status.hp_current <= 0 {
-                        runstate.event = Option::from(GameEvent::Lose);
-                        state.set(AppState::InGameEvent).unwrap();
-                        player.battle_state = PlayerBattleState::Select
+                let living_enemies: Vec<(usize, i32)> = enemy_status_query
+                    .iter_mut()
+                    .filter(|(enemy_status, ..)| enemy_status.hp_current > 0)
+                    .map(|(enemy_status, _, _, slot, ..)| (slot.0, enemy_status.speed))
+                    .collect();
+                let mut rng = rand::thread_rng();
+                // 次に行動する者。倒れた者は飛ばし、全員行動したら次のラウンドの順番を決める
+                let next = loop {
+                    if battle.turns.is_empty() {
+                        // 防御はそのラウンドの間だけ
+                        for (mut member_status, ..) in party_query.iter_mut() {
+                            member_status.start_round();
+                        }
+                        for (mut enemy_status, ..) in enemy_status_query.iter_mut() {
+                            enemy_status.start_round();
+                        }
+                        let mut combatants: Vec<(Combatant, i32)> = party_query
+                            .iter_mut()
+                            .filter(|(member_status, ..)| member_status.hp_current > 0)
+                            .map(|(member_status, _, member)| {
+                                (Combatant::Party(member.order), member_status.speed)
+                            })
+                            .collect();
+                        combatants.sort_unstable_by_key(|(combatant, _)| match combatant {
+                            Combatant::Party(order) => *order,
+                            Combatant::Enemy(slot) => *slot,
+                        });
+                        combatants.extend(
+                            living_enemies
+                                .iter()
+                                .map(|&(slot, speed)| (Combatant::Enemy(slot), speed)),
+                        );
+                        battle.turns = turn_order(&combatants, &mut rng).into_iter().collect();
+                    }
+                    match battle.turns.pop_front() {
+                        Some(Combatant::Party(order)) if alive.contains(&order) => {
+                            break Combatant::Party(order)
+                        }
+                        Some(Combatant::Enemy(slot))
+                            if living_enemies.iter().any(|&(living, _)| living == slot) =>
+                        {
+                            break Combatant::Enemy(slot)
+                        }
+                        Some(_) => continue,
+                        None => unreachable!("both sides have someone standing"),
+                    }
+                };
+                match next {
+                    Combatant::Party(order) => {
+                        // 毒で倒れたり眠っていたりすると番が飛ぶ
+                        let mut skipped = false;
+                        for (mut member_status, _, member) in party_query.iter_mut() {
+                            if member.order != order {
+                                continue;
+                            }
+                            let stopped_by = member_status.stopped_by();
+                            let poison = member_status.tick_ailments();
+                            send_ailment_effects(&mut effect_events, stopped_by, poison, None);
+                            skipped = stopped_by.is_some() || member_status.hp_current <= 0;
+                        }
+                        if !skipped {
+                            battle.actor = order;
+                            player.battle_state = PlayerBattleState::Select;
+                        }
+                    }
+                    Combatant::Enemy(next) => {
+                        let defender = alive[rng.gen_range(0..alive.len())];
+                        for (mut enemy_status, mut ai, _, slot, ..) in enemy_status_query.iter_mut()
+                        {
+                            if slot.0 != next {
+                                continue;
+                            }
+                            let stopped_by = enemy_status.stopped_by();
+                            let poison = enemy_status.tick_ailments();
+                            send_ailment_effects(
+                                &mut effect_events,
+                                stopped_by,
+                                poison,
+                                Some(slot.0),
+                            );
+                            if stopped_by.is_some() || enemy_status.hp_current <= 0 {
+                                continue;
+                            }
+                            let skill = &ai.choose(&enemy_status, &mut rng);
+                            if *skill == Skill::Defend {
+                                enemy_status.defending = true;
+                                effect_events.send(EffectEvent {
+                                    kind: EffectKind::Defend,
+                                    damage_or_heal: 0,
+                                    is_player_attack: false,
+                                    target: Some(slot.0),
+                                    hit: Hit::Normal,
+                                });
+                                continue;
+                            }
+                            for (mut member_status, _, member) in party_query.iter_mut() {
+                                if member.order != defender {
+                                    continue;
+                                }
+                                let (dmg, hit) = attack(
+                                    &mut enemy_status,
+                                    &mut member_status,
+                                    *skill,
+                                    element_data.element(skill),
+                                    &Affinities::default(),
+                                    &mut rng,
+                                );
+                                effect_events.send(EffectEvent {
+                                    kind: skill_to_effect(*skill),
+                                    damage_or_heal: dmg,
+                                    is_player_attack: false,
+                                    target: Some(slot.0),
+                                    hit,
+                                });
+                            }
+                        }
                     }
                 }
             }
+            PlayerBattleState::Select | PlayerBattleState::Target => {}
         }
     }
 }
 
+// Poison damage at the start of a turn, or the ailment that took the turn away
+fn send_ailment_effects(
+    effect_events: &mut EventWriter<EffectEvent>,
+    stopped_by: Option<Ailment>,
+    poison: i32,
+    target: Option<usize>,
+) {
+    let (ailment, damage) = match (stopped_by, poison) {
+        (_, poison) if poison > 0 => (Ailment::Poison, poison),
+        (Some(ailment), _) => (ailment, 0),
+        (None, _) => return,
+    };
+    effect_events.send(EffectEvent {
+        kind: EffectKind::Ailment(ailment),
+        damage_or_heal: damage,
+        is_player_attack: false,
+        target,
+        hit: Hit::Normal,
+    });
+}
+
+// Chance to run away. Faster and stronger heroes get away more often.
+fn run_chance(runner: &CharacterStatus, enemies: &[CharacterStatus]) -> f64 {
+    let fastest = enemies.iter().map(|enemy| enemy.speed).max().unwrap_or(0);
+    let highest = enemies.iter().map(|enemy| enemy.lv).max().unwrap_or(0);
+    let by_speed = (runner.speed - fastest) as f64 / (2 * runner.speed.max(fastest).max(1)) as f64;
+    let by_level = (runner.lv - highest) as f64 * 0.05;
+    (0.5 + by_speed + by_level).clamp(0.1, 0.95)
+}
+
+// None when the party gets away, otherwise the slot of the enemy that gets a free hit
+fn run_away<R: Rng>(
+    runner: &CharacterStatus,
+    enemies: &[(usize, CharacterStatus)],
+    rng: &mut R,
+) -> Option<usize> {
+    let statuses: Vec<CharacterStatus> = enemies.iter().map(|(_, status)| status.clone()).collect();
+    if rng.gen_bool(run_chance(runner, &statuses)) {
+        return None;
+    }
+    // 逃げ損ねると敵に一度攻撃される
+    Some(enemies[rng.gen_range(0..enemies.len())].0)
+}
+
+// Halves the damage taken until the next round and returns the MP recovered
+fn defend(status: &mut CharacterStatus) -> i32 {
+    // 防御すると少しMPが回復する
+    let mp = (status.mp_max / 20).max(1);
+    status.mp_current = (status.mp_current + mp).clamp(0, status.mp_max);
+    status.defending = true;
+    mp
+}
+
+// MP is paid once per action even when a spell hits every enemy
+fn pay_mp(status: &mut CharacterStatus, skill: Skill) -> bool {
+    let (_, _, _, mp) = skill2param(status, status, skill);
+    if status.mp_current < mp {
+        return false;
+    }
+    status.mp_current = (status.mp_current - mp).clamp(0, 999);
+    true
+}
+
 // 攻撃計算
-fn attack(
+fn attack<R: Rng>(
     own_status: &mut CharacterStatus,
     other_status: &mut CharacterStatus,
     skill: Skill,
-) -> i32 {
-    let (attack, defence, heal, mp) = skill2param(own_status, other_status, skill);
-    if own_status.mp_current < mp {
-        0
+    element: Element,
+    affinities: &Affinities,
+    rng: &mut R,
+) -> (i32, Hit) {
+    let (attack, defence, heal, _mp) = skill2param(own_status, other_status, skill);
+    if heal > 0 {
+        own_status.hp_current = (own_status.hp_current + heal).clamp(1, own_status.hp_max);
+        (heal, Hit::Normal)
     } else {
-        own_status.mp_current = (own_status.mp_current - mp).clamp(0, 999);
-        if heal > 0 {
-            own_status.hp_current = (own_status.hp_current + heal).clamp(1, own_status.hp_max);
-            heal
+        // 呪文は必ず当たるが会心もない
+        let mut critical = false;
+        if !skill.is_spell() {
+            let hit_chance = (own_status.accuracy - other_status.evasion).clamp(5, 100);
+            if rng.gen_range(0..100) >= hit_chance {
+                return (0, Hit::Miss);
+            }
+            critical = rng.gen_range(0..100) < own_status.critical;
+        }
+        // 会心の一撃は守備力を無視する
+        let guard = if critical {
+            0
         } else {
-            let mut rng = rand::thread_rng();
-            let mut dmg = attack + rng.gen_range(0..attack) - rng.gen_range(0..defence);
-            dmg = dmg.clamp(1, 999);
-            other_status.hp_current = (other_status.hp_current - dmg).clamp(0, 999);
-            dmg
+            rng.gen_range(0..defence)
+        };
+        let dmg = attack + rng.gen_range(0..attack) - guard;
+        // 弱点なら大きく、耐性があれば小さく
+        let (mut dmg, hit) = affinities.scale(element, dmg);
+        // 無効な属性には会心も効かない
+        let immune = affinities.immune(element);
+        let hit = if critical && !immune {
+            Hit::Critical
+        } else {
+            hit
+        };
+        // 防御中はダメージ半減
+        if other_status.defending {
+            dmg /= 2;
         }
+        // 無効でなければ最低 1 は通る
+        dmg = if immune { 0 } else { dmg.clamp(1, 999) };
+        other_status.hp_current = (other_status.hp_current - dmg).clamp(0, 999);
+        // 殴られると目が覚める
+        other_status.cure(&[Ailment::Sleep]);
+        if let Some((ailment, chance, turns)) = skill.ailment() {
+            if rng.gen_bool(chance) {
+                other_status.inflict(ailment, turns);
+            }
+        }
+        (dmg, hit)
     }
 }
 
@@ -161,7 +608,9 @@ fn skill2param(
     skill: Skill,
 ) -> (i32, i32, i32, i32) {
     match skill {
-        Skill::Sword => (own_status.attack / 2, other_status.defence, 0, 0),
+        Skill::Sword | Skill::Bite | Skill::Curse => {
+            (own_status.attack / 2, other_status.defence, 0, 0)
+        }
         Skill::Spell(item) => {
             let spl = [0, 1, 3, 6];
             match item {
@@ -178,11 +627,247 @@ fn skill2param(
                     (25 * lv) as i32,
                 ),
                 Item::SpellIce(lv) => (spl[lv as usize] * 15, 1, 0, (25 * lv) as i32),
+                Item::SpellCure(lv) => (0, 0, 0, (10 * lv) as i32),
                 _ => panic!("unexpected item"),
             }
         }
         Skill::Arrow => (own_status.attack / 2, other_status.defence / 4, 0, 0),
         Skill::Wind => (own_status.attack / 2, other_status.defence / 2, 0, 0),
         Skill::Death => (own_status.attack / 2, other_status.defence, 0, 0),
+        Skill::Item(_) | Skill::Defend | Skill::Run => (0, 0, 0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn status(accuracy: i32, evasion: i32, critical: i32) -> CharacterStatus {
+        CharacterStatus {
+            accuracy,
+            evasion,
+            critical,
+            ..Default::default()
+        }
+    }
+
+    // (damage, hit) of `times` attacks, the target is healed after each one
+    fn attacks(
+        own: &CharacterStatus,
+        other: &CharacterStatus,
+        skill: Skill,
+        times: usize,
+    ) -> Vec<(i32, Hit)> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..times)
+            .map(|_| {
+                let mut target = other.clone();
+                let result = attack(
+                    &mut own.clone(),
+                    &mut target,
+                    skill,
+                    Element::Physical,
+                    &Affinities::default(),
+                    &mut rng,
+                );
+                assert_eq!(target.hp_current, other.hp_current - result.0);
+                result
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_miss_deals_no_damage() {
+        let results = attacks(&status(50, 50, 0), &status(95, 5, 0), Skill::Sword, 200);
+        assert!(results.contains(&(0, Hit::Miss)));
+        assert!(results
+            .iter()
+            .all(|(dmg, hit)| (*hit == Hit::Miss) == (*dmg == 0)));
+    }
+
+    #[test]
+    fn at_least_one_in_twenty_attacks_hits() {
+        let results = attacks(&status(0, 5, 0), &status(95, 100, 0), Skill::Sword, 2000);
+        let hits = results.iter().filter(|(_, hit)| *hit != Hit::Miss).count();
+        // 5% は 100 回
+        assert!(50 < hits && hits < 150, "{} hits", hits);
+    }
+
+    #[test]
+    fn accurate_attacks_never_miss() {
+        let results = attacks(&status(300, 5, 0), &status(95, 5, 0), Skill::Sword, 500);
+        assert!(results.iter().all(|(_, hit)| *hit != Hit::Miss));
+    }
+
+    #[test]
+    fn immune_targets_take_no_damage() {
+        let mut own = status(300, 5, 50);
+        own.attack = 100;
+        let mut rng = StdRng::seed_from_u64(0);
+        let immune = Affinities(vec![(Element::Dark, 0.)]);
+        for _ in 0..200 {
+            let mut target = status(95, 5, 0);
+            target.hp_current = 100;
+            let (dmg, hit) = attack(
+                &mut own.clone(),
+                &mut target,
+                Skill::Curse,
+                Element::Dark,
+                &immune,
+                &mut rng,
+            );
+            assert_eq!((dmg, hit), (0, Hit::Resisted));
+            assert_eq!(target.hp_current, 100);
+        }
+    }
+
+    #[test]
+    fn critical_hits_ignore_defence() {
+        let mut own = status(300, 5, 100);
+        own.attack = 100;
+        let mut wall = status(95, 5, 0);
+        wall.defence = 999;
+        // attack / 2 から attack まで
+        for (dmg, hit) in attacks(&own, &wall, Skill::Sword, 200) {
+            assert_eq!(hit, Hit::Critical);
+            assert!(50 <= dmg && dmg < 100, "{}", dmg);
+        }
+        own.critical = 0;
+        let results = attacks(&own, &wall, Skill::Sword, 200);
+        assert!(results.iter().all(|(_, hit)| *hit == Hit::Normal));
+        assert!(results.iter().any(|(dmg, _)| *dmg < 50));
+    }
+
+    #[test]
+    fn spells_neither_miss_nor_crit() {
+        let results = attacks(
+            &status(0, 5, 100),
+            &status(95, 100, 0),
+            Skill::Spell(Item::SpellFire(1)),
+            200,
+        );
+        assert!(results.iter().all(|(_, hit)| *hit == Hit::Normal));
+    }
+
+    fn runner(speed: i32, lv: i32) -> CharacterStatus {
+        CharacterStatus {
+            speed,
+            lv,
+            ..Default::default()
+        }
+    }
+
+    fn assert_chance(chance: f64, expected: f64) {
+        assert!(
+            (chance - expected).abs() < 1e-9,
+            "{} != {}",
+            chance,
+            expected
+        );
+    }
+
+    #[test]
+    fn faster_runners_get_away_more_often() {
+        let enemy = runner(10, 1);
+        assert_chance(run_chance(&runner(10, 1), &[enemy.clone()]), 0.5);
+        assert_chance(run_chance(&runner(20, 1), &[enemy.clone()]), 0.75);
+        assert_chance(run_chance(&runner(5, 1), &[enemy]), 0.25);
+        // 一番速い敵と比べる
+        assert_chance(
+            run_chance(&runner(10, 1), &[runner(5, 1), runner(20, 1)]),
+            0.25,
+        );
+    }
+
+    #[test]
+    fn higher_levels_get_away_more_often() {
+        assert_chance(run_chance(&runner(10, 5), &[runner(10, 1)]), 0.7);
+        assert_chance(run_chance(&runner(10, 1), &[runner(10, 3)]), 0.4);
+        assert_chance(
+            run_chance(&runner(10, 5), &[runner(10, 1), runner(10, 7)]),
+            0.4,
+        );
+    }
+
+    #[test]
+    fn running_is_never_sure() {
+        assert_chance(run_chance(&runner(999, 50), &[runner(1, 1)]), 0.95);
+        assert_chance(run_chance(&runner(1, 1), &[runner(999, 50)]), 0.1);
+    }
+
+    #[test]
+    fn a_failed_escape_gives_a_living_enemy_a_free_hit() {
+        let enemies = vec![(0, runner(999, 50)), (2, runner(999, 50))];
+        let mut rng = StdRng::seed_from_u64(0);
+        let chasers: Vec<Option<usize>> = (0..1000)
+            .map(|_| run_away(&runner(1, 1), &enemies, &mut rng))
+            .collect();
+        let escapes = chasers.iter().filter(|chaser| chaser.is_none()).count();
+        // 10% は 100 回
+        assert!(50 < escapes && escapes < 150, "{} escapes", escapes);
+        assert!(chasers.contains(&Some(0)));
+        assert!(chasers.contains(&Some(2)));
+        assert!(!chasers.contains(&Some(1)));
+    }
+
+    #[test]
+    fn defending_halves_every_hit_of_the_round() {
+        let mut own = status(300, 5, 0);
+        own.attack = 200;
+        let mut guard = status(95, 5, 0);
+        guard.hp_current = 999;
+        let normal = attacks(&own, &guard, Skill::Sword, 50);
+        defend(&mut guard);
+        assert!(guard.defending);
+        // 同じ乱数なら半分になる
+        let defended = attacks(&own, &guard, Skill::Sword, 50);
+        for ((normal, _), (defended, _)) in normal.iter().zip(defended.iter()) {
+            assert_eq!(*defended, normal / 2);
+        }
+        // 殴られても防御は解けない
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..3 {
+            attack(
+                &mut own.clone(),
+                &mut guard,
+                Skill::Sword,
+                Element::Physical,
+                &Affinities::default(),
+                &mut rng,
+            );
+        }
+        assert!(guard.defending);
+    }
+
+    #[test]
+    fn defending_ends_with_the_round() {
+        let mut guard = CharacterStatus::default();
+        defend(&mut guard);
+        guard.start_round();
+        assert!(!guard.defending);
+        defend(&mut guard);
+        guard.end_battle();
+        assert!(!guard.defending);
+    }
+
+    #[test]
+    fn defending_recovers_a_twentieth_of_the_mp() {
+        let mut guard = CharacterStatus {
+            mp_max: 200,
+            mp_current: 50,
+            ..Default::default()
+        };
+        assert_eq!(defend(&mut guard), 10);
+        assert_eq!(guard.mp_current, 60);
+        // 少なくとも 1 は回復し、最大値は超えない
+        let mut guard = CharacterStatus {
+            mp_max: 10,
+            mp_current: 10,
+            ..Default::default()
+        };
+        assert_eq!(defend(&mut guard), 1);
+        assert_eq!(guard.mp_current, 10);
     }
 }
@@ -1,5 +1,11 @@
 use crate::actions::{Action, PlayerActions};
-use crate::map::{Map, Position, MAP_SIZE};
+use crate::character_status::CharacterStatus;
+use crate::dungeon::Dungeon;
+use crate::equipment::EquipMenu;
+use crate::events::GameEvent;
+use crate::inventory::{Inventory, Item};
+use crate::map::{Field, Map, Position, MAP_SIZE};
+use crate::party::PartyMember;
 use crate::setup::{MapCamera, MapCameraState};
 use crate::AppState;
 use bevy::prelude::*;
@@ -9,23 +15,48 @@ pub struct ExploreActionsPlugin;
 // This plugin execute actions from user input on Explore scene.
 impl Plugin for ExploreActionsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system_set(
+        app.init_resource::<FieldMenu>().add_system_set(
             SystemSet::on_update(AppState::InGameExplore)
                 .with_system(update_position.system())
+                .with_system(use_item_menu.system())
                 .after("movement"),
         );
     }
 }
 
+// The "Use item" menu of the explore screen, opened with Enter
+#[derive(Default)]
+pub struct FieldMenu {
+    pub open: bool,
+    // index into field_items, the one after the last is "Close"
+    pub cursor: usize,
+    // item waiting for the hero it is used on
+    pub using: Option<Item>,
+    pub target: usize,
+    // what happened, e.g. "Knight recovered 60."
+    pub message: String,
+}
+
+// Consumables of the hero, every one of them works on the map
+pub fn field_items(inventory: &Inventory) -> Vec<Item> {
+    inventory
+        .consumables
+        .iter()
+        .map(|(item, _)| *item)
+        .collect()
+}
+
 fn update_position(
     actions: Res<PlayerActions>,
     map: Res<Map>,
+    field_menu: Res<FieldMenu>,
+    equip_menu: Res<EquipMenu>,
     mut player_camera_query: Query<(&mut MapCamera, &mut Transform, &mut Position)>,
 ) {
     if let Some((mut map_camera, mut transform, mut position)) =
         player_camera_query.iter_mut().next()
     {
-        if matches!(actions.action, None) {
+        if matches!(actions.action, None) || field_menu.open || equip_menu.open {
             return;
         }
         if matches!(map_camera.state, MapCameraState::Moving) {
@@ -50,6 +81,9 @@ fn update_position(
             return;
         }
         map_camera.destination = new_position;
+        if !map.wrap {
+            return;
+        }
 
         // マップ端からワープする場合、次の位置と現在の位置の両方を更新する
         let width = MAP_SIZE[0] as f32;
@@ -104,3 +138,178 @@ fn update_position(
         }
     }
 }
+
+fn use_item_menu(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut menu: ResMut<FieldMenu>,
+    equip_menu: Res<EquipMenu>,
+    map: Res<Map>,
+    dungeon: Res<Dungeon>,
+    mut party_query: Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
+    mut camera_query: Query<(&mut Transform, &mut Position, &mut MapCamera)>,
+    mut events_writer: EventWriter<GameEvent>,
+) {
+    if !menu.open {
+        if !equip_menu.open && keyboard_input.just_pressed(KeyCode::Return) {
+            keyboard_input.reset(KeyCode::Return);
+            *menu = FieldMenu {
+                open: true,
+                ..Default::default()
+            };
+        }
+        return;
+    }
+    let party_size = party_query.iter_mut().count();
+    let items = match party_query
+        .iter_mut()
+        .find(|(_, _, member)| member.order == 0)
+    {
+        Some((_, inventory, _)) => field_items(&inventory),
+        None => return,
+    };
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        menu.open = false;
+    } else if keyboard_input.just_pressed(KeyCode::Up) || keyboard_input.just_pressed(KeyCode::W) {
+        if menu.using.is_some() {
+            menu.target = menu.target.saturating_sub(1);
+        } else {
+            menu.cursor = menu.cursor.saturating_sub(1);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Down) || keyboard_input.just_pressed(KeyCode::S)
+    {
+        if menu.using.is_some() {
+            menu.target = (menu.target + 1).min(party_size - 1);
+        } else {
+            menu.cursor = (menu.cursor + 1).min(items.len());
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        keyboard_input.reset(KeyCode::Return);
+        if let Some(item) = menu.using.take() {
+            let target = menu.target;
+            menu.message = use_item(item, target, &mut party_query);
+        } else {
+            match items.get(menu.cursor) {
+                None => menu.open = false,
+                Some(Item::Wing) if dungeon.is_inside() => {
+                    menu.message = "The wing can't fly in the castle.".to_string();
+                }
+                Some(Item::Wing) => {
+                    fly(&map, &mut camera_query, &mut events_writer);
+                    consume(Item::Wing, &mut party_query);
+                    menu.open = false;
+                }
+                // 仲間がいれば誰に使うか選ぶ
+                Some(item) if party_size > 1 => {
+                    menu.using = Some(*item);
+                    menu.target = 0;
+                }
+                Some(item) => menu.message = use_item(*item, 0, &mut party_query),
+            }
+        }
+        // 使い切った道具は一覧から消える
+        if let Some((_, inventory, _)) = party_query
+            .iter_mut()
+            .find(|(_, _, member)| member.order == 0)
+        {
+            menu.cursor = menu.cursor.min(field_items(&inventory).len());
+        }
+    }
+}
+
+// Uses a consumable of the hero on a member, returns what happened
+fn use_item(
+    item: Item,
+    target: usize,
+    party_query: &mut Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
+) -> String {
+    let mut message = None;
+    for (mut status, _, member) in party_query.iter_mut() {
+        if member.order == target {
+            let effect = status.use_item(&item);
+            message = item_message(&status.name, item, effect);
+        }
+    }
+    // 効き目がなければ道具は残る
+    match message {
+        Some(message) => {
+            consume(item, party_query);
+            message
+        }
+        None => "It had no effect.".to_string(),
+    }
+}
+
+// What using the item did, None when nothing changed
+fn item_message(name: &str, item: Item, (hp, mp, cured): (i32, i32, bool)) -> Option<String> {
+    if hp > 0 || mp > 0 {
+        Some(format!("{} recovered {}.", name, hp.max(mp)))
+    } else if cured {
+        Some(format!("The {} cured {}.", item, name))
+    } else {
+        None
+    }
+}
+
+fn consume(
+    item: Item,
+    party_query: &mut Query<(&mut CharacterStatus, &mut Inventory, &PartyMember)>,
+) {
+    for (_, mut inventory, member) in party_query.iter_mut() {
+        if member.order == 0 {
+            inventory.consume(&item);
+        }
+    }
+}
+
+// The wing flies back to the last town and enters it
+fn fly(
+    map: &Map,
+    camera_query: &mut Query<(&mut Transform, &mut Position, &mut MapCamera)>,
+    events_writer: &mut EventWriter<GameEvent>,
+) {
+    let town = map.last_town.unwrap_or(map.start);
+    let destination = Position {
+        x: town.0 as f32,
+        y: town.1 as f32,
+    };
+    for (mut transform, mut position, mut map_camera) in camera_query.iter_mut() {
+        *transform = map.position_to_translation(&destination, transform.translation.z);
+        *position = destination;
+        *map_camera = MapCamera {
+            destination,
+            ..Default::default()
+        };
+    }
+    if let Some(Field::Town { item, visited }) = map.fields.get(&town) {
+        events_writer.send(GameEvent::TownArrived(*item, *visited));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items_without_effect_are_kept() {
+        assert_eq!(item_message("You", Item::Herb, (0, 0, false)), None);
+        assert_eq!(item_message("You", Item::Antidote, (0, 0, false)), None);
+    }
+
+    #[test]
+    fn the_message_tells_what_was_restored() {
+        assert_eq!(
+            item_message("Knight", Item::Herb, (20, 0, false)),
+            Some("Knight recovered 20.".to_string())
+        );
+        assert_eq!(
+            item_message("Mage", Item::MagicWater, (0, 40, false)),
+            Some("Mage recovered 40.".to_string())
+        );
+        assert_eq!(
+            item_message("Priest", Item::Antidote, (0, 0, true)),
+            Some("The Antidote cured Priest.".to_string())
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use crate::inventory::Item;
+use anyhow::bail;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub struct GrowthPlugin;
+
+// This plugin loads how heroes grow with their level
+impl Plugin for GrowthPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Growth>()
+            .add_asset::<Growth>()
+            .init_asset_loader::<GrowthLoader>()
+            .add_startup_system(load_growth.system())
+            .add_system(apply_growth.system());
+    }
+}
+
+// Exp can't grow past this
+pub const MAX_EXP: i32 = 999_999;
+
+// Exp table and stat curves of the heroes, JSON in `assets/data/heroes.growth`.
+//
+//   "exp"    total exp needed for every level, starting with 0 for Lv 1
+//   "stats"  a curve for each stat, its value at Lv n is
+//            base + rate * (n - 1) ^ power
+//            rate is 0 and power is 1 when left out
+//   "learn"  spells every hero learns by name, as [level, spell] pairs,
+//            e.g. "Mage": [[5, { "SpellIce": 1 }]]
+//
+// Equipment is added on top, see CharacterStatus::update_stats.
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "be4e6a7d-f287-4994-85b0-e828712782e5"]
+pub struct Growth {
+    pub exp: Vec<i32>,
+    pub stats: Curves,
+    #[serde(default)]
+    pub learn: HashMap<String, Vec<(i32, Item)>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Curves {
+    pub hp: Curve,
+    pub mp: Curve,
+    pub attack: Curve,
+    pub defence: Curve,
+    pub speed: Curve,
+    pub accuracy: Curve,
+    pub evasion: Curve,
+    pub critical: Curve,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Curve {
+    pub base: i32,
+    #[serde(default)]
+    pub rate: f32,
+    #[serde(default = "linear")]
+    pub power: f32,
+}
+
+fn linear() -> f32 {
+    1.
+}
+
+impl Curve {
+    pub fn at(&self, lv: i32) -> i32 {
+        let levels = (lv - 1).max(0) as f32;
+        self.base + (self.rate * levels.powf(self.power)).round() as i32
+    }
+}
+
+// The copy built into the game is used until the asset has loaded
+const BUILT_IN: &str = include_str!("../../assets/data/heroes.growth");
+
+impl Default for Growth {
+    fn default() -> Self {
+        parse_growth(BUILT_IN).expect("the built-in growth table is broken")
+    }
+}
+
+impl Growth {
+    pub fn level(&self, exp: i32) -> i32 {
+        self.exp.iter().filter(|&&e| e <= exp).count() as i32
+    }
+    // Spells the hero learns after Lv `from` up to Lv `to`
+    pub fn learned(&self, name: &str, from: i32, to: i32) -> Vec<Item> {
+        self.learn.get(name).map_or(Vec::new(), |spells| {
+            spells
+                .iter()
+                .filter(|(lv, _)| from < *lv && *lv <= to)
+                .map(|(_, spell)| *spell)
+                .collect()
+        })
+    }
+}
+
+pub fn parse_growth(text: &str) -> anyhow::Result<Growth> {
+    let growth: Growth = serde_json::from_str(text)?;
+    if growth.exp.first() != Some(&0) {
+        bail!("the exp table must start with 0 for Lv 1");
+    }
+    // 経験値は必ず増えていく
+    if let Some(i) = (1..growth.exp.len()).find(|&i| growth.exp[i] <= growth.exp[i - 1]) {
+        bail!("Lv {} must need more exp than Lv {}", i + 1, i);
+    }
+    if growth.exp.last().map_or(false, |&exp| exp > MAX_EXP) {
+        bail!("no level can need more than {} exp", MAX_EXP);
+    }
+    for (name, spells) in growth.learn.iter() {
+        for (lv, spell) in spells.iter() {
+            if spell.can_use().is_none() {
+                bail!("{} can't learn {}, it is not a spell", name, spell);
+            }
+            if *lv < 2 || *lv > growth.exp.len() as i32 {
+                bail!(
+                    "{} learns {} at Lv {}, which is never reached",
+                    name,
+                    spell,
+                    lv
+                );
+            }
+        }
+    }
+    Ok(growth)
+}
+
+#[derive(Default)]
+pub struct GrowthLoader;
+
+impl AssetLoader for GrowthLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let growth = parse_growth(std::str::from_utf8(bytes)?)?;
+            load_context.set_default_asset(LoadedAsset::new(growth));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["growth"]
+    }
+}
+
+// Kept so the asset is not unloaded
+pub struct GrowthHandle(pub Handle<Growth>);
+
+fn load_growth(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(GrowthHandle(asset_server.load("data/heroes.growth")));
+}
+
+// A changed file is used from the next level up or change of equipment
+fn apply_growth(
+    mut events: EventReader<AssetEvent<Growth>>,
+    growth_handle: Res<GrowthHandle>,
+    assets: Res<Assets<Growth>>,
+    mut growth: ResMut<Growth>,
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle }
+                if *handle == growth_handle.0 =>
+            {
+                if let Some(loaded) = assets.get(handle) {
+                    *growth = loaded.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character_status::{CharacterStatus, Skill};
+    use crate::inventory::Inventory;
+
+    fn table(exp: &str) -> String {
+        format!(
+            r#"{{"exp": {}, "stats": {{
+                "hp": {{"base": 100, "rate": 25}},
+                "mp": {{"base": 100, "rate": 25}},
+                "attack": {{"base": 10, "rate": 5}},
+                "defence": {{"base": 10, "rate": 5}},
+                "speed": {{"base": 10, "rate": 2, "power": 1.5}},
+                "accuracy": {{"base": 95}},
+                "evasion": {{"base": 5}},
+                "critical": {{"base": 5}}
+            }}}}"#,
+            exp
+        )
+    }
+
+    #[test]
+    fn the_built_in_table_goes_past_999_exp() {
+        let growth = Growth::default();
+        assert_eq!(growth.level(0), 1);
+        assert_eq!(growth.level(999), 32);
+        assert_eq!(growth.level(MAX_EXP), growth.exp.len() as i32);
+        assert!(growth.level(MAX_EXP) > 32);
+    }
+
+    #[test]
+    fn curves_follow_the_formula() {
+        let growth = parse_growth(&table("[0, 10, 30]")).unwrap();
+        assert_eq!(growth.stats.attack.at(1), 10);
+        assert_eq!(growth.stats.attack.at(4), 25);
+        assert_eq!(growth.stats.accuracy.at(30), 95);
+        // 2 * 4 ^ 1.5
+        assert_eq!(growth.stats.speed.at(5), 26);
+    }
+
+    #[test]
+    fn a_level_up_tells_what_it_raised() {
+        let growth = Growth::default();
+        let mut status = CharacterStatus::default();
+        let mut inventory = Inventory::default();
+        assert_eq!(status.add_exp(5, &growth, &mut inventory), None);
+        let levelup = status.add_exp(5, &growth, &mut inventory).unwrap();
+        assert_eq!(
+            levelup.to_string(),
+            "You reached Lv 2!\nAttack +5, Defence +5, Speed +3, HP +25, MP +25"
+        );
+        assert_eq!(status.hp_current, 125);
+    }
+
+    #[test]
+    fn exp_goes_past_999() {
+        let growth = Growth::default();
+        let mut status = CharacterStatus::default();
+        status.add_exp(1500, &growth, &mut Inventory::default());
+        assert_eq!(status.exp, 1500);
+        assert_eq!(status.lv, growth.level(1500));
+        assert!(status.lv > 32);
+    }
+
+    #[test]
+    fn spells_are_learned_on_the_way_up() {
+        let growth = Growth::default();
+        let mut status = CharacterStatus::default();
+        let mut inventory = Inventory::default();
+        // Lv 1 から Lv 3 へ
+        let levelup = status.add_exp(20, &growth, &mut inventory).unwrap();
+        assert_eq!(levelup.learned, vec![Skill::Spell(Item::SpellHeal(1))]);
+        assert!(levelup.to_string().ends_with("\nYou learned SpellHeal(1)!"));
+        assert_eq!(
+            inventory.skills,
+            vec![Skill::Sword, Skill::Spell(Item::SpellHeal(1))]
+        );
+        assert_eq!(growth.learned("Nobody", 1, 50), vec![]);
+    }
+
+    #[test]
+    fn a_spell_is_not_learned_twice() {
+        let growth = Growth::default();
+        let mut status = CharacterStatus::default();
+        let mut inventory = Inventory::default();
+        // 町で先に見つけた呪文
+        inventory.add_item(Item::SpellHeal(1));
+        let levelup = status.add_exp(20, &growth, &mut inventory).unwrap();
+        assert!(levelup.learned.is_empty());
+        assert_eq!(inventory.skills.len(), 2);
+        // 覚えた後で見つけても増えない
+        status.add_exp(100, &growth, &mut inventory);
+        inventory.add_item(Item::SpellFire(1));
+        assert_eq!(
+            inventory
+                .skills
+                .iter()
+                .filter(|skill| **skill == Skill::Spell(Item::SpellFire(1)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn only_spells_can_be_learned() {
+        let text = BUILT_IN.replacen("{ \"SpellHeal\": 1 }", "\"Herb\"", 1);
+        let err = parse_growth(&text).unwrap_err();
+        assert_eq!(err.to_string(), "You can't learn Herb, it is not a spell");
+    }
+
+    #[test]
+    fn the_exp_table_must_grow() {
+        assert!(parse_growth(&table("[]")).is_err());
+        assert!(parse_growth(&table("[5, 10]")).is_err());
+        let err = parse_growth(&table("[0, 10, 10]")).unwrap_err();
+        assert_eq!(err.to_string(), "Lv 3 must need more exp than Lv 2");
+    }
+}
@@ -1,7 +1,10 @@
-use crate::character_status::Skill;
+use crate::character_status::{Ailment, Skill};
+use crate::equipment::{Bonus, Equipment, Slot};
 use bevy::prelude::*;
 use core::fmt;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
+use std::str::FromStr;
 
 pub struct InventoryPlugin;
 
@@ -12,11 +15,12 @@ impl Plugin for InventoryPlugin {
     }
 }
 
-#[derive(Clone, Eq, PartialEq, Copy, Debug, Hash)]
+#[derive(Clone, Eq, PartialEq, Copy, Debug, Hash, Serialize, Deserialize)]
 pub enum Item {
     SpellHeal(u32),
     SpellFire(u32),
     SpellIce(u32),
+    SpellCure(u32),
     IronBody,
     IronArm,
     IronLeg,
@@ -24,6 +28,16 @@ pub enum Item {
     HeroSword,
     WisdomRing,
     FairyShield,
+    CopperSword,
+    IronSword,
+    LeatherShield,
+    LeatherArmor,
+    SpeedRing,
+    // consumables, kept in stacks
+    Antidote,
+    Herb,
+    MagicWater,
+    Wing,
 }
 
 impl Display for Item {
@@ -31,15 +45,133 @@ impl Display for Item {
         write!(fmt, "{:?}", self)
     }
 }
+// Parses the Debug representation, e.g. "IronBody" or "SpellFire(2)"
+impl FromStr for Item {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, lv) = match s.find('(') {
+            Some(open) if s.ends_with(')') => {
+                let lv = s[open + 1..s.len() - 1]
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid spell level in '{}'", s))?;
+                if !(1..=3).contains(&lv) {
+                    return Err(format!("spell level must be 1 to 3 in '{}'", s));
+                }
+                (&s[..open], Some(lv))
+            }
+            _ => (s, None),
+        };
+        match (name, lv) {
+            ("SpellHeal", Some(lv)) => Ok(Item::SpellHeal(lv)),
+            ("SpellFire", Some(lv)) => Ok(Item::SpellFire(lv)),
+            ("SpellIce", Some(lv)) => Ok(Item::SpellIce(lv)),
+            ("SpellCure", Some(lv)) => Ok(Item::SpellCure(lv)),
+            ("IronBody", None) => Ok(Item::IronBody),
+            ("IronArm", None) => Ok(Item::IronArm),
+            ("IronLeg", None) => Ok(Item::IronLeg),
+            ("IronHead", None) => Ok(Item::IronHead),
+            ("HeroSword", None) => Ok(Item::HeroSword),
+            ("WisdomRing", None) => Ok(Item::WisdomRing),
+            ("FairyShield", None) => Ok(Item::FairyShield),
+            ("CopperSword", None) => Ok(Item::CopperSword),
+            ("IronSword", None) => Ok(Item::IronSword),
+            ("LeatherShield", None) => Ok(Item::LeatherShield),
+            ("LeatherArmor", None) => Ok(Item::LeatherArmor),
+            ("SpeedRing", None) => Ok(Item::SpeedRing),
+            ("Antidote", None) => Ok(Item::Antidote),
+            ("Herb", None) => Ok(Item::Herb),
+            ("MagicWater", None) => Ok(Item::MagicWater),
+            ("Wing", None) => Ok(Item::Wing),
+            _ => Err(format!("unknown item '{}'", s)),
+        }
+    }
+}
+
 impl Item {
     pub fn can_use(&self) -> Option<Skill> {
         match self {
             Self::SpellFire(lv) => Some(Skill::Spell(Item::SpellFire(*lv))),
             Self::SpellHeal(lv) => Some(Skill::Spell(Item::SpellHeal(*lv))),
             Self::SpellIce(lv) => Some(Skill::Spell(Item::SpellIce(*lv))),
+            Self::SpellCure(lv) => Some(Skill::Spell(Item::SpellCure(*lv))),
+            _ => None,
+        }
+    }
+    // Used up one at a time instead of learned
+    pub fn is_consumable(&self) -> bool {
+        matches!(
+            self,
+            Self::Antidote | Self::Herb | Self::MagicWater | Self::Wing
+        )
+    }
+    // The wing only flies on the map
+    pub fn usable_in_battle(&self) -> bool {
+        self.is_consumable() && *self != Self::Wing
+    }
+    // (HP, MP) restored by using it on a hero
+    pub fn restores(&self) -> (i32, i32) {
+        match self {
+            Self::Herb => (60, 0),
+            Self::MagicWater => (0, 40),
+            _ => (0, 0),
+        }
+    }
+    // Where it is worn, None for spells and consumables
+    pub fn slot(&self) -> Option<Slot> {
+        match self {
+            Self::CopperSword | Self::IronSword | Self::HeroSword => Some(Slot::Weapon),
+            Self::LeatherShield | Self::FairyShield => Some(Slot::Shield),
+            Self::IronHead => Some(Slot::Head),
+            Self::LeatherArmor | Self::IronBody => Some(Slot::Body),
+            Self::IronArm => Some(Slot::Arms),
+            Self::IronLeg => Some(Slot::Legs),
+            Self::WisdomRing | Self::SpeedRing => Some(Slot::Accessory),
             _ => None,
         }
     }
+    // Added to the stats while it is worn
+    pub fn bonus(&self) -> Bonus {
+        let (hp, mp, attack, defence, speed, critical) = match self {
+            Self::CopperSword => (0, 0, 6, 0, 0, 0),
+            Self::IronSword => (0, 0, 15, 0, 0, 0),
+            Self::HeroSword => (0, 0, 40, 0, 0, 20),
+            Self::LeatherShield => (0, 0, 0, 5, 0, 0),
+            Self::FairyShield => (0, 0, 0, 30, 0, 0),
+            Self::IronHead => (25, 0, 0, 4, 0, 0),
+            Self::LeatherArmor => (10, 0, 0, 6, 0, 0),
+            Self::IronBody => (50, 0, 0, 10, 0, 0),
+            Self::IronArm => (30, 0, 0, 4, 0, 0),
+            Self::IronLeg => (30, 0, 0, 4, 0, 0),
+            Self::WisdomRing => (0, 120, 0, 0, 0, 0),
+            Self::SpeedRing => (0, 0, 0, 0, 15, 0),
+            _ => (0, 0, 0, 0, 0, 0),
+        };
+        Bonus {
+            hp,
+            mp,
+            attack,
+            defence,
+            speed,
+            critical,
+        }
+    }
+    // Ailments cured by using it on a hero
+    pub fn cures(&self) -> &'static [Ailment] {
+        match self {
+            Self::SpellCure(1) | Self::Antidote => &[Ailment::Poison],
+            Self::SpellCure(2) => &[Ailment::Poison, Ailment::Sleep, Ailment::Paralysis],
+            Self::SpellCure(_) => &[
+                Ailment::Poison,
+                Ailment::Sleep,
+                Ailment::Paralysis,
+                Ailment::Silence,
+            ],
+            _ => &[],
+        }
+    }
 }
 
 pub fn generate_items() -> Vec<Item> {
@@ -53,6 +185,9 @@ pub fn generate_items() -> Vec<Item> {
         Item::SpellIce(1),
         Item::SpellIce(2),
         Item::SpellIce(3),
+        Item::SpellCure(1),
+        Item::SpellCure(2),
+        Item::SpellCure(3),
         Item::IronBody,
         Item::IronArm,
         Item::IronLeg,
@@ -60,20 +195,36 @@ pub fn generate_items() -> Vec<Item> {
         Item::HeroSword,
         Item::WisdomRing,
         Item::FairyShield,
+        Item::Antidote,
     ]
 }
 
+// Most of one consumable a hero can carry
+pub const MAX_STACK: u32 = 99;
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Inventory {
     pub items: Vec<Item>,
     pub skills: Vec<Skill>,
+    // skills known without carrying their spell book, from joining or a level up
+    pub learned: Vec<Skill>,
     pub selected_skill_index: usize,
+    // only the player's hero carries gold
+    pub gold: u32,
+    // consumables and how many of each
+    pub consumables: Vec<(Item, u32)>,
+    pub equipment: Equipment,
 }
 impl Default for Inventory {
     fn default() -> Self {
         Inventory {
             items: Vec::new(),
             skills: vec![Skill::Sword],
+            learned: vec![Skill::Sword],
             selected_skill_index: 0,
+            gold: 0,
+            consumables: Vec::new(),
+            equipment: Equipment::default(),
         }
     }
 }
@@ -84,7 +235,17 @@ impl Display for Inventory {
             "Items:\n{0}",
             self.items
                 .iter()
-                .map(|s| format!("  {}", s))
+                // E は装備中
+                .map(|s| if self.equipment.is_equipped(s) {
+                    format!("E {}", s)
+                } else {
+                    format!("  {}", s)
+                })
+                .chain(
+                    self.consumables
+                        .iter()
+                        .map(|(item, count)| format!("  {} x{}", item, count)),
+                )
                 .collect::<Vec<_>>()
                 .join("\n")
         )
@@ -92,34 +253,146 @@ impl Display for Inventory {
 }
 
 impl Inventory {
-    pub fn skill_list(&self) -> String {
+    // The battle menu, skills and items followed by the commands every hero has.
+    // The items are the consumables of the player's hero, the whole party uses them.
+    pub fn commands(&self, stash: &[(Item, u32)]) -> Vec<Skill> {
+        let mut commands = self.skills.clone();
+        commands.extend(
+            stash
+                .iter()
+                .filter(|(item, _)| item.usable_in_battle())
+                .map(|(item, _)| Skill::Item(*item)),
+        );
+        commands.push(Skill::Defend);
+        commands.push(Skill::Run);
+        commands
+    }
+    // Commands that can't be used in this battle are marked
+    pub fn skill_list(&self, stash: &[(Item, u32)], usable: impl Fn(&Skill) -> bool) -> String {
         let mut ret = String::new();
-        for (i, s) in self.skills.iter().enumerate() {
-            if i == self.selected_skill_index {
-                ret.push_str(&format!("> {}\n", s));
+        for (i, s) in self.commands(stash).iter().enumerate() {
+            let cursor = if i == self.selected_skill_index {
+                ">"
             } else {
-                ret.push_str(&format!("  {}\n", s));
+                " "
+            };
+            let name = match s {
+                Skill::Item(item) => format!("{} x{}", item, count(stash, item)),
+                _ => s.to_string(),
+            };
+            if usable(s) {
+                ret.push_str(&format!("{} {}\n", cursor, name));
+            } else {
+                ret.push_str(&format!("{} {} (x)\n", cursor, name));
             }
         }
         ret
     }
     pub fn add_item(&mut self, item: Item) {
+        if item.is_consumable() {
+            match self.consumables.iter_mut().find(|(i, _)| *i == item) {
+                Some((_, count)) => *count = (*count + 1).min(MAX_STACK),
+                None => self.consumables.push((item, 1)),
+            }
+            return;
+        }
         self.items.push(item);
+        // gear is put on when nothing is worn in its slot
+        if let Some(slot) = item.slot() {
+            if self.equipment.get(slot).is_none() {
+                self.equipment.equip(item);
+            }
+        }
         if let Some(skill) = item.can_use() {
-            self.skills.push(skill);
+            self.add_skill(skill);
+        }
+    }
+    // A spell of the learn table, it stays when a book of it is sold.
+    // Returns the skill when it is new.
+    pub fn learn(&mut self, spell: Item) -> Option<Skill> {
+        let skill = spell.can_use()?;
+        if !self.learned.contains(&skill) {
+            self.learned.push(skill);
+        }
+        self.add_skill(skill)
+    }
+    // Spells already known, from a town or a level up, are not added twice
+    fn add_skill(&mut self, skill: Skill) -> Option<Skill> {
+        if self.skills.contains(&skill) {
+            return None;
+        }
+        self.skills.push(skill);
+        Some(skill)
+    }
+    pub fn count(&self, item: &Item) -> u32 {
+        count(&self.consumables, item)
+    }
+    // Uses up one of the stack, false when there is none left
+    pub fn consume(&mut self, item: &Item) -> bool {
+        match self.consumables.iter_mut().find(|(i, _)| i == item) {
+            Some((_, count)) => *count -= 1,
+            None => return false,
         }
+        self.consumables.retain(|(_, count)| *count > 0);
+        true
     }
-    pub fn increment_index(&mut self) {
+    // Equipment is worn once, so only one of each can be bought
+    pub fn buy(&mut self, item: Item, price: u32) -> Result<(), &'static str> {
+        if self.items.contains(&item) {
+            return Err("You already have one.");
+        }
+        if self.count(&item) >= MAX_STACK {
+            return Err("You can't carry any more.");
+        }
+        if self.gold < price {
+            return Err("Not enough gold.");
+        }
+        self.gold -= price;
+        self.add_item(item);
+        Ok(())
+    }
+    pub fn sell(&mut self, item: Item, price: u32) {
+        if self.consume(&item) {
+            self.gold += price;
+            return;
+        }
+        if let Some(index) = self.items.iter().position(|i| *i == item) {
+            self.items.remove(index);
+            if let Some(slot) = item.slot().filter(|_| self.equipment.is_equipped(&item)) {
+                self.equipment.unequip(slot);
+            }
+            // 覚えた呪文や他の本でも使える呪文は忘れない
+            if let Some(skill) = item.can_use() {
+                let kept = self.learned.contains(&skill)
+                    || self.items.iter().any(|i| i.can_use() == Some(skill));
+                if !kept {
+                    self.skills.retain(|s| *s != skill);
+                }
+            }
+            self.selected_skill_index = 0;
+            self.gold += price;
+        }
+    }
+    pub fn increment_index(&mut self, stash: &[(Item, u32)]) {
         self.selected_skill_index =
-            (&self.selected_skill_index + 1).clamp(0, self.skills.len() - 1);
+            (&self.selected_skill_index + 1).clamp(0, self.commands(stash).len() - 1);
     }
-    pub fn decrement_index(&mut self) {
+    pub fn decrement_index(&mut self, stash: &[(Item, u32)]) {
         if self.selected_skill_index > 0 {
             self.selected_skill_index =
-                (&self.selected_skill_index - 1).clamp(0, self.skills.len() - 1);
+                (&self.selected_skill_index - 1).clamp(0, self.commands(stash).len() - 1);
         }
     }
-    pub fn skill(&self) -> Skill {
-        self.skills[self.selected_skill_index]
+    pub fn skill(&self, stash: &[(Item, u32)]) -> Skill {
+        let commands = self.commands(stash);
+        commands[self.selected_skill_index.min(commands.len() - 1)]
     }
 }
+
+// How many of the item are in the stack
+fn count(stash: &[(Item, u32)], item: &Item) -> u32 {
+    stash
+        .iter()
+        .find(|(i, _)| i == item)
+        .map_or(0, |(_, count)| *count)
+}
@@ -1,29 +1,53 @@
 mod actions;
 mod audio;
 mod battle_actions;
+mod boss;
 mod character_status;
+mod dungeon;
 mod effects;
+mod element;
 mod enemies;
+mod enemy_ai;
+mod equipment;
 mod event_actions;
 mod events;
 mod explore_actions;
+mod growth;
 mod inventory;
 mod loading;
 mod map;
 mod menu;
+mod party;
 mod player;
+mod save;
+mod save_backend;
+mod save_migrations;
+mod save_slots;
 mod setup;
+mod shop;
+mod terrain;
+mod tiled;
+mod turn_order;
 mod ui;
+mod world_map;
 
 use crate::audio::InternalAudioPlugin;
 use crate::battle_actions::BattleActionsPlugin;
+use crate::boss::BossPlugin;
+use crate::dungeon::DungeonPlugin;
+use crate::element::ElementPlugin;
 use crate::enemies::EnemiesPlugin;
+use crate::equipment::EquipmentPlugin;
 use crate::explore_actions::ExploreActionsPlugin;
+use crate::growth::GrowthPlugin;
 use crate::inventory::InventoryPlugin;
 use crate::loading::LoadingPlugin;
 use crate::map::MapPlugin;
 use crate::menu::MenuPlugin;
+use crate::party::PartyPlugin;
 use crate::player::PlayerPlugin;
+use crate::save::SavePlugin;
+use crate::save_slots::SaveSlotsPlugin;
 use crate::ui::UiPlugin;
 
 use crate::actions::ActionsPlugin;
@@ -31,6 +55,7 @@ use crate::effects::EffectsPlugin;
 use crate::event_actions::EventActionsPlugin;
 use crate::events::EventsPlugin;
 use crate::setup::SetupPlugin;
+use crate::shop::ShopPlugin;
 use bevy::app::AppBuilder;
 #[cfg(debug_assertions)]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
@@ -50,6 +75,8 @@ pub enum AppState {
     InGameExplore,
     InGameBattle,
     InGameEvent,
+    // Choosing a save slot to load or save
+    SaveSlots,
     // Here the menu is drawn and waiting for player interaction
     Menu,
 }
@@ -59,10 +86,16 @@ impl Plugin for GamePlugin {
         app.add_state(AppState::Loading)
             .add_plugin(SetupPlugin)
             .add_plugin(EnemiesPlugin)
+            .add_plugin(ElementPlugin)
+            .add_plugin(BossPlugin)
+            .add_plugin(GrowthPlugin)
             .add_plugin(InventoryPlugin)
+            .add_plugin(ShopPlugin)
+            .add_plugin(EquipmentPlugin)
             .add_plugin(LoadingPlugin)
             .add_plugin(MenuPlugin)
             .add_plugin(MapPlugin)
+            .add_plugin(DungeonPlugin)
             .add_plugin(ActionsPlugin)
             .add_plugin(ExploreActionsPlugin)
             .add_plugin(BattleActionsPlugin)
@@ -71,7 +104,10 @@ impl Plugin for GamePlugin {
             .add_plugin(EffectsPlugin)
             .add_plugin(InternalAudioPlugin)
             .add_plugin(UiPlugin)
-            .add_plugin(PlayerPlugin);
+            .add_plugin(PlayerPlugin)
+            .add_plugin(PartyPlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(SaveSlotsPlugin);
 
         #[cfg(debug_assertions)]
         {
@@ -3,6 +3,7 @@ use crate::effects::EffectKind;
 use crate::enemies::Enemy;
 use crate::menu::UiTitleText;
 use crate::setup::ForState;
+use crate::world_map::WorldMapAsset;
 use crate::AppState;
 use bevy::prelude::*;
 use bevy_asset_loader::{AssetCollection, AssetLoader};
@@ -26,12 +27,13 @@ impl Plugin for LoadingPlugin {
             .with_collection::<EffectsSpriteSheet>()
             .init_resource::<EffectsAtlas>()
             .build(app);
-        app.add_system_set(
-            SystemSet::on_enter(AppState::Loading).with_system(setup_loading.system()),
-        )
-        .add_system_set(
-            SystemSet::on_update(AppState::Loading).with_system(update_loading.system()),
-        );
+        app.add_startup_system(load_map_assets.system())
+            .add_system_set(
+                SystemSet::on_enter(AppState::Loading).with_system(setup_loading.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Loading).with_system(update_loading.system()),
+            );
     }
 }
 
@@ -58,6 +60,8 @@ pub struct AudioAssets {
     pub bgm_battle_last: Handle<AudioSource>,
     #[asset(path = "audio/se/se_maoudamashii_battle01.ogg")]
     pub attack: Handle<AudioSource>,
+    #[asset(path = "audio/se/se_critical.wav")]
+    pub critical: Handle<AudioSource>,
     #[asset(path = "audio/se/se_maoudamashii_retro08.ogg")]
     pub heal: Handle<AudioSource>,
     #[asset(path = "audio/se/se_maoudamashii_retro22.ogg")]
@@ -73,6 +77,7 @@ impl AudioAssets {
             AudioKind::BGMLose => self.bgm_lose.clone(),
             AudioKind::BGMWin => self.bgm_win.clone(),
             AudioKind::SEAttack => self.attack.clone(),
+            AudioKind::SECritical => self.critical.clone(),
             AudioKind::SEHeal => self.heal.clone(),
             AudioKind::SETown => self.town.clone(),
         }
@@ -99,6 +104,7 @@ impl TextureAssets {
             &Enemy::Goblin => self.enemy_goblin.clone(),
             &Enemy::Skeleton => self.enemy_skeleton.clone(),
             &Enemy::Griffin => self.enemy_griffin.clone(),
+            &Enemy::Archer => self.enemy_skeleton.clone(),
             &Enemy::Boss => self.enemy_lich.clone(),
         }
     }
@@ -130,13 +136,13 @@ impl FromWorld for TileMapAtlas {
             tilemap: atlases.add(TextureAtlas::from_grid(
                 assets.tilemap.clone(),
                 Vec2::new(16., 16.),
-                6,
+                12,
                 1,
             )),
             mini_tilemap: atlases.add(TextureAtlas::from_grid(
                 assets.mini_tilemap.clone(),
                 Vec2::new(1., 1.),
-                8,
+                12,
                 1,
             )),
         }
@@ -267,6 +273,9 @@ impl EffectsAtlas {
             &EffectKind::Death => self.death.clone(),
             &EffectKind::Arrow => self.arrow.clone(),
             &EffectKind::Wind => self.wind.clone(),
+            &EffectKind::Defend => self.heal.clone(),
+            &EffectKind::Cure => self.heal.clone(),
+            &EffectKind::Ailment(_) => self.death.clone(),
         }
     }
     pub fn get_length_for_effect(&self, kind: &EffectKind) -> i32 {
@@ -278,10 +287,27 @@ impl EffectsAtlas {
             &EffectKind::Death => 8,
             &EffectKind::Arrow => 9,
             &EffectKind::Wind => 8,
+            &EffectKind::Defend => 8,
+            &EffectKind::Cure => 8,
+            &EffectKind::Ailment(_) => 8,
         }
     }
 }
 
+// World maps are not part of the collections above, so a broken map file
+// only logs an error instead of blocking the loading state forever.
+pub struct MapAssets {
+    pub story: Handle<WorldMapAsset>,
+    pub tiled: Handle<WorldMapAsset>,
+}
+
+fn load_map_assets(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(MapAssets {
+        story: asset_server.load("maps/story.map"),
+        tiled: asset_server.load("maps/story.tmx"),
+    });
+}
+
 fn setup_loading(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
@@ -1,20 +1,34 @@
+use crate::dungeon::Dungeon;
 use crate::inventory::{generate_items, Item};
-use crate::loading::TileMapAtlas;
+use crate::loading::{MapAssets, TileMapAtlas};
+use crate::save::PendingLoad;
 use crate::setup::{render_layer, MapCamera, RenderLayer};
+use crate::terrain::TerrainKind;
+use crate::tiled::TiledMapLoader;
+use crate::world_map::{WorldMapAsset, WorldMapLoader};
 use crate::AppState;
 use bevy::prelude::*;
 use bevy_tilemap::prelude::{GridTopology, LayerKind, TilemapBundle, TilemapDefaultPlugins};
 use bevy_tilemap::{Tile, Tilemap, TilemapLayer};
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 pub struct MapPlugin;
 
 // This plugin is responsible to generate and update map
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        let map = Map::generate_map();
-        app.insert_resource(map)
+        let seed = WorldSeed::default();
+        let map = Map::generate_map(seed.value, TerrainKind::default());
+        app.insert_resource(seed)
+            .insert_resource(MapSource::default())
+            .insert_resource(map)
+            .add_asset::<WorldMapAsset>()
+            .init_asset_loader::<WorldMapLoader>()
+            .init_asset_loader::<TiledMapLoader>()
             .add_plugins(TilemapDefaultPlugins)
             .add_system_set(
                 SystemSet::on_enter(AppState::InGameMap)
@@ -43,13 +57,97 @@ impl Plugin for MapPlugin {
 pub struct TileMap;
 pub struct MiniMap;
 
+// Where the world map comes from, selectable on the title screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MapSource {
+    Generated(TerrainKind),
+    Authored,
+    Tiled,
+}
+impl Default for MapSource {
+    fn default() -> Self {
+        MapSource::Generated(TerrainKind::default())
+    }
+}
+impl fmt::Display for MapSource {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapSource::Generated(terrain) => write!(fmt, "{}", terrain),
+            MapSource::Authored => write!(fmt, "Authored"),
+            MapSource::Tiled => write!(fmt, "Tiled"),
+        }
+    }
+}
+impl MapSource {
+    pub fn next(&self) -> Self {
+        match self {
+            MapSource::Generated(TerrainKind::Scatter) => MapSource::Generated(TerrainKind::Noise),
+            MapSource::Generated(TerrainKind::Noise) => MapSource::Authored,
+            MapSource::Authored => MapSource::Tiled,
+            MapSource::Tiled => MapSource::Generated(TerrainKind::Scatter),
+        }
+    }
+}
+
+// Seed that drives every random decision in map generation.
+// The same seed always yields the same world on native and web.
+#[derive(Clone, Copy, Debug)]
+pub struct WorldSeed {
+    pub value: u64,
+    // true when the seed was given by the player (command line, URL or title screen)
+    pub requested: bool,
+}
+impl Default for WorldSeed {
+    fn default() -> Self {
+        match requested_seed() {
+            Some(value) => WorldSeed {
+                value,
+                requested: true,
+            },
+            None => WorldSeed::random(),
+        }
+    }
+}
+impl WorldSeed {
+    pub fn random() -> Self {
+        WorldSeed {
+            value: rand::thread_rng().gen_range(0..1_000_000_000),
+            requested: false,
+        }
+    }
+}
+
+// `--seed 1234` or `--seed=1234` on the command line
+#[cfg(not(target_arch = "wasm32"))]
+fn requested_seed() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == "--seed" {
+            args.get(i + 1).and_then(|s| s.parse().ok())
+        } else {
+            arg.strip_prefix("--seed=").and_then(|s| s.parse().ok())
+        }
+    })
+}
+
+// `?seed=1234` in the page URL
+#[cfg(target_arch = "wasm32")]
+fn requested_seed() -> Option<u64> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|kv| kv.strip_prefix("seed="))
+        .and_then(|s| s.parse().ok())
+}
+
 #[derive(Default, Copy, Clone, PartialEq, Debug)]
 pub struct Position {
     pub x: f32,
     pub y: f32,
 }
 
-#[derive(Clone, Eq, PartialEq, Hash)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Serialize, Deserialize)]
 pub enum Field {
     Grass,
     Forest,
@@ -59,6 +157,11 @@ pub enum Field {
     Castle,
     Player, // for minimap
     Blink,  // for minimap
+    Floor,
+    Wall,
+    StairsUp,
+    StairsDown,
+    Throne,
 }
 impl Field {
     pub fn sprite_index(&self) -> usize {
@@ -74,8 +177,25 @@ impl Field {
             Field::Castle => 5,
             Field::Player => 6,
             Field::Blink => 7,
+            Field::Floor => 8,
+            Field::Wall => 9,
+            Field::StairsUp => 10,
+            Field::StairsDown => 11,
+            Field::Throne => 5,
         }
     }
+    pub fn is_obstacle(&self) -> bool {
+        matches!(self, Field::Water | Field::Wall)
+    }
+}
+
+// Overrides the encounters of the field inside an area of an authored map
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EncounterZone {
+    // 1 / rate chance per step, 0 disables encounters
+    pub rate: Option<i32>,
+    // field whose enemy table is used
+    pub table: Option<Field>,
 }
 
 pub const MAP_SIZE: [u32; 2] = [64, 48];
@@ -84,13 +204,21 @@ pub const CHUNK_SIZE: [u32; 2] = [3, 3];
 
 #[derive(Default)]
 pub struct Map {
+    pub seed: u64,
+    pub source: MapSource,
     pub width: u32,
     pub height: u32,
     pub tile_size: f32,
+    pub start: (i32, i32),
+    // where the wing flies to, the start when no town was visited since loading
+    pub last_town: Option<(i32, i32)>,
+    // dungeon floors do not loop at the edges
+    pub wrap: bool,
     pub collisions: HashSet<(i32, i32)>,
     pub blinks_on_mini_tiles: HashSet<(i32, i32)>,
     pub blink_status: bool,
     pub fields: HashMap<(i32, i32), Field>,
+    pub encounter_zones: HashMap<(i32, i32), EncounterZone>,
     pub tiles: Vec<Tile<(i32, i32)>>,
     pub mini_tiles: Vec<Tile<(i32, i32)>>,
 }
@@ -129,107 +257,122 @@ impl Map {
         }
     }
 
-    pub fn generate_map() -> Self {
-        let width = MAP_SIZE[0] as i32;
-        let height = MAP_SIZE[1] as i32;
+    // Move the start position, e.g. when returning from a dungeon
+    pub fn move_start(&mut self, start: (i32, i32)) {
+        let old_start = self.start;
+        for tile in self.mini_tiles.iter_mut() {
+            if tile.point == old_start {
+                tile.sprite_index = self.fields[&old_start].sprite_index();
+            }
+            if tile.point == start {
+                tile.sprite_index = Field::Player.sprite_index();
+            }
+        }
+        self.start = start;
+    }
+
+    // Field whose enemy table is used for encounters at the position
+    pub fn encounter_field(&self, point: &Position) -> Field {
+        let field = self.position_to_field(point);
+        if !matches!(
+            field,
+            Field::Grass | Field::Forest | Field::Mountain | Field::Floor
+        ) {
+            return field;
+        }
+        match self.encounter_zones.get(&(point.x as i32, point.y as i32)) {
+            Some(EncounterZone {
+                table: Some(table), ..
+            }) => table.clone(),
+            _ => field,
+        }
+    }
+
+    pub fn encounter_rate(&self, point: &Position) -> Option<i32> {
+        self.encounter_zones
+            .get(&(point.x as i32, point.y as i32))
+            .and_then(|zone| zone.rate)
+    }
+
+    pub fn generate_map(seed: u64, terrain: TerrainKind) -> Self {
+        // StdRng may change between rand versions and platforms, ChaCha8 does not
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        loop {
+            let mut fields = generate_fields(&mut rng, terrain);
+            if connect_unreachable_fields(&mut fields) {
+                return Map::from_fields(seed, MapSource::Generated(terrain), fields);
+            }
+            // 繋がらなければ同じ種の続きで作り直す
+            warn!("world {} has unreachable towns, generating it again", seed);
+        }
+    }
+
+    // Build tiles, collisions and mini map blinks from fields
+    pub fn from_fields(seed: u64, source: MapSource, fields: HashMap<(i32, i32), Field>) -> Self {
+        Map::from_world_map(
+            seed,
+            source,
+            WorldMapAsset {
+                fields,
+                ..Default::default()
+            },
+        )
+    }
+
+    // Tiles on the background layer default to the field sprite,
+    // the foreground layer only holds what the world map defines.
+    pub fn from_world_map(seed: u64, source: MapSource, world_map: WorldMapAsset) -> Self {
         let mut map = Map {
+            seed,
+            source,
             width: MAP_SIZE[0],
             height: MAP_SIZE[1],
             tile_size: MAP_TEXTURE_SIZE[0] as f32,
+            start: world_map.start,
+            last_town: None,
+            wrap: true,
             collisions: HashSet::new(),
             blinks_on_mini_tiles: HashSet::new(),
             blink_status: false,
-            fields: HashMap::with_capacity((width * height) as usize),
+            fields: world_map.fields,
+            encounter_zones: world_map.encounter_zones,
             tiles: Vec::new(),
             mini_tiles: Vec::new(),
         };
 
-        for y in 0..height {
-            for x in 0..width {
-                // -chunk_width/2 < x < chunk_width/2,  -chunk_height/2 < y < chunk_height/2
-                let pos = (x - width / 2, y - height / 2); // -chunk_height/2 < y < chunk_height/2
-                map.fields.insert(pos, Field::Grass);
+        for (pos, field) in map.fields.iter() {
+            let mut layers = vec![(
+                render_layer(RenderLayer::MapBackGround),
+                world_map
+                    .background
+                    .get(pos)
+                    .copied()
+                    .unwrap_or(field.sprite_index()),
+            )];
+            if let Some(&sprite_index) = world_map.foreground.get(pos) {
+                layers.push((render_layer(RenderLayer::MapForeGround), sprite_index));
             }
-        }
-
-        let mut rng = rand::thread_rng();
-        for y in 0..height {
-            for x in 0..width {
-                let pos = (x - width / 2, y - height / 2); // -chunk_height/2 < y < chunk_height/2
-                if rng.gen_bool(1. / 60.) {
-                    let max = rng.gen_range(10..60);
-                    for _i in 0..max {
-                        let pos = (
-                            (pos.0 + rng.gen_range(-3..4)).clamp(-&width / 2, &width / 2 - 1),
-                            (pos.1 + rng.gen_range(-3..4)).clamp(-&height / 2, &height / 2 - 1),
-                        );
-                        map.fields.insert(pos, Field::Mountain);
+            for (sprite_order, sprite_index) in layers {
+                for x in 0..CHUNK_SIZE[0] as i32 {
+                    for y in 0..CHUNK_SIZE[1] as i32 {
+                        let y = y - CHUNK_SIZE[1] as i32 / 2;
+                        let x = x - CHUNK_SIZE[0] as i32 / 2;
+                        let tile = Tile {
+                            point: (
+                                pos.0 + x * MAP_SIZE[0] as i32,
+                                pos.1 + y * MAP_SIZE[1] as i32,
+                            ),
+                            sprite_order,
+                            sprite_index,
+                            ..Default::default()
+                        };
+                        map.tiles.push(tile);
                     }
                 }
-                if 0 < y && y < height - 1 && 0 < x && x < width - 1 {
-                    if rng.gen_bool(1. / 12.) {
-                        map.fields.insert(pos, Field::Water);
-                    }
-                }
-                if rng.gen_bool(1. / 6.) {
-                    map.fields.insert(pos, Field::Forest);
-                }
-            }
-        }
-
-        for y in -1..2 {
-            for x in -1..2 {
-                map.fields.insert((x, y), Field::Grass);
-            }
-        }
-
-        let castle_x =
-            (width as f32 * rng_multi_range((0.05, 0.2), (0.8, 0.95))) as i32 - width / 2;
-        let castle_y =
-            (height as f32 * rng_multi_range((0.05, 0.2), (0.8, 0.95))) as i32 - height / 2;
-        map.fields.insert((castle_x, castle_y), Field::Castle);
-
-        for item in generate_items() {
-            let town_x =
-                (width as f32 * rng_multi_range((0.05, 0.45), (0.55, 0.95))) as i32 - width / 2;
-            let town_y =
-                (height as f32 * rng_multi_range((0.05, 0.45), (0.55, 0.95))) as i32 - height / 2;
-            match map.fields[&(town_x, town_y)] {
-                Field::Town {
-                    item: _,
-                    visited: _,
-                } => continue,
-                Field::Castle => continue,
-                _ => {}
-            }
-            map.fields.insert(
-                (town_x, town_y),
-                Field::Town {
-                    item,
-                    visited: false,
-                },
-            );
-        }
-
-        for (pos, field) in map.fields.iter_mut() {
-            for x in 0..CHUNK_SIZE[0] as i32 {
-                for y in 0..CHUNK_SIZE[1] as i32 {
-                    let y = y - CHUNK_SIZE[1] as i32 / 2;
-                    let x = x - CHUNK_SIZE[0] as i32 / 2;
-                    let tile = Tile {
-                        point: (
-                            pos.0 + x * MAP_SIZE[0] as i32,
-                            pos.1 + y * MAP_SIZE[1] as i32,
-                        ),
-                        sprite_index: field.sprite_index(),
-                        ..Default::default()
-                    };
-                    map.tiles.push(tile);
-                }
             }
             let mini_tile = Tile {
                 point: pos.clone(),
-                sprite_index: if pos.0 == 0 && pos.1 == 0 {
+                sprite_index: if *pos == map.start {
                     Field::Player.sprite_index()
                 } else {
                     field.sprite_index()
@@ -237,7 +380,7 @@ impl Map {
                 ..Default::default()
             };
             map.mini_tiles.push(mini_tile.clone());
-            if matches!(field, Field::Water) {
+            if field.is_obstacle() {
                 map.collisions.insert(pos.clone());
             }
             match field {
@@ -247,7 +390,7 @@ impl Map {
                 } => {
                     map.blinks_on_mini_tiles.insert(pos.clone());
                 }
-                Field::Castle => {
+                Field::Castle | Field::Throne | Field::StairsUp | Field::StairsDown => {
                     map.blinks_on_mini_tiles.insert(pos.clone());
                 }
                 _ => {}
@@ -256,24 +399,209 @@ impl Map {
 
         map
     }
+
+    // Inverse of from_world_map, layers are read back from the center chunk
+    pub fn to_world_map(&self) -> WorldMapAsset {
+        let mut world_map = WorldMapAsset {
+            fields: self.fields.clone(),
+            start: self.start,
+            encounter_zones: self.encounter_zones.clone(),
+            ..Default::default()
+        };
+        for tile in self.tiles.iter() {
+            let field = match self.fields.get(&tile.point) {
+                Some(field) => field,
+                None => continue,
+            };
+            if tile.sprite_order == render_layer(RenderLayer::MapForeGround) {
+                world_map.foreground.insert(tile.point, tile.sprite_index);
+            } else if tile.sprite_index != field.sprite_index() {
+                world_map.background.insert(tile.point, tile.sprite_index);
+            }
+        }
+        world_map
+    }
+}
+
+// Terrain, castle and towns of a generated world, before the towns are connected
+fn generate_fields(rng: &mut ChaCha8Rng, terrain: TerrainKind) -> HashMap<(i32, i32), Field> {
+    let width = MAP_SIZE[0] as i32;
+    let height = MAP_SIZE[1] as i32;
+    let mut fields = HashMap::with_capacity((width * height) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            // -chunk_width/2 < x < chunk_width/2,  -chunk_height/2 < y < chunk_height/2
+            let pos = (x - width / 2, y - height / 2); // -chunk_height/2 < y < chunk_height/2
+            fields.insert(pos, Field::Grass);
+        }
+    }
+
+    terrain.generator().generate(rng, &mut fields);
+
+    for y in -1..2 {
+        for x in -1..2 {
+            fields.insert((x, y), Field::Grass);
+        }
+    }
+
+    let castle_x =
+        (width as f32 * rng_multi_range(rng, (0.05, 0.2), (0.8, 0.95))) as i32 - width / 2;
+    let castle_y =
+        (height as f32 * rng_multi_range(rng, (0.05, 0.2), (0.8, 0.95))) as i32 - height / 2;
+    fields.insert((castle_x, castle_y), Field::Castle);
+
+    for item in generate_items() {
+        let town_x =
+            (width as f32 * rng_multi_range(rng, (0.05, 0.45), (0.55, 0.95))) as i32 - width / 2;
+        let town_y =
+            (height as f32 * rng_multi_range(rng, (0.05, 0.45), (0.55, 0.95))) as i32 - height / 2;
+        match fields[&(town_x, town_y)] {
+            Field::Town {
+                item: _,
+                visited: _,
+            } => continue,
+            Field::Castle => continue,
+            _ => {}
+        }
+        fields.insert(
+            (town_x, town_y),
+            Field::Town {
+                item,
+                visited: false,
+            },
+        );
+    }
+
+    fields
+}
+
+// Wrap a position around the torus the explore scene moves on
+pub fn wrap_position(pos: (i32, i32)) -> (i32, i32) {
+    let width = MAP_SIZE[0] as i32;
+    let height = MAP_SIZE[1] as i32;
+    (
+        (pos.0 + width / 2).rem_euclid(width) - width / 2,
+        (pos.1 + height / 2).rem_euclid(height) - height / 2,
+    )
+}
+
+// Towns, the castle, stairs and the throne that cannot be walked to from the start position
+pub fn unreachable_fields(
+    fields: &HashMap<(i32, i32), Field>,
+    start: (i32, i32),
+) -> Vec<(i32, i32)> {
+    let mut reached = HashSet::new();
+    let mut stack = vec![start];
+    while let Some(pos) = stack.pop() {
+        match fields.get(&pos) {
+            Some(field) if !field.is_obstacle() => {}
+            _ => continue,
+        }
+        if !reached.insert(pos) {
+            continue;
+        }
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)].iter() {
+            stack.push(wrap_position((pos.0 + dx, pos.1 + dy)));
+        }
+    }
+
+    let mut unreachable: Vec<(i32, i32)> = fields
+        .iter()
+        .filter(|(pos, field)| {
+            matches!(
+                field,
+                Field::Town { .. }
+                    | Field::Castle
+                    | Field::StairsUp
+                    | Field::StairsDown
+                    | Field::Throne
+            ) && !reached.contains(*pos)
+        })
+        .map(|(pos, _field)| *pos)
+        .collect();
+    unreachable.sort();
+    unreachable
+}
+
+pub fn is_fully_reachable(fields: &HashMap<(i32, i32), Field>, start: (i32, i32)) -> bool {
+    unreachable_fields(fields, start).is_empty()
+}
+
+// Dig a path through obstacles from every unreachable town or castle to the start position,
+// false when some are still unreachable afterwards
+fn connect_unreachable_fields(fields: &mut HashMap<(i32, i32), Field>) -> bool {
+    let width = MAP_SIZE[0] as i32;
+    let height = MAP_SIZE[1] as i32;
+    for target in unreachable_fields(fields, (0, 0)) {
+        // shortest way on the torus
+        let dx = wrap_position((-target.0, 0)).0;
+        let dy = wrap_position((0, -target.1)).1;
+        let mut pos = target;
+        for _ in 0..dx.abs().min(width) {
+            pos = wrap_position((pos.0 + dx.signum(), pos.1));
+            clear_obstacle(fields, pos);
+        }
+        for _ in 0..dy.abs().min(height) {
+            pos = wrap_position((pos.0, pos.1 + dy.signum()));
+            clear_obstacle(fields, pos);
+        }
+    }
+    is_fully_reachable(fields, (0, 0))
+}
+
+fn clear_obstacle(fields: &mut HashMap<(i32, i32), Field>, pos: (i32, i32)) {
+    if fields.get(&pos).map_or(false, |field| field.is_obstacle()) {
+        fields.insert(pos, Field::Grass);
+    }
 }
 
 fn regenerate_map(
     mut commands: Commands,
     mut map: ResMut<Map>,
+    seed: Res<WorldSeed>,
+    source: Res<MapSource>,
+    map_assets: Res<MapAssets>,
+    world_maps: Res<Assets<WorldMapAsset>>,
+    mut dungeon: ResMut<Dungeon>,
+    pending_load: Res<PendingLoad>,
     tilemap: Query<Entity, With<TileMap>>,
 ) {
-    let new_map = Map::generate_map();
-    map.collisions = new_map.collisions.clone();
-    map.blinks_on_mini_tiles = new_map.blinks_on_mini_tiles.clone();
-    map.blink_status = new_map.blink_status.clone();
-    map.fields = new_map.fields.clone();
-    map.tiles = new_map.tiles.clone();
-    map.mini_tiles = new_map.mini_tiles.clone();
-
     for entity in tilemap.iter() {
         commands.entity(entity).despawn_recursive();
     }
+
+    if let Some(save) = pending_load.save.as_ref() {
+        *dungeon = Dungeon::default();
+        *map = save.to_map();
+        return;
+    }
+
+    if let Some(transition) = dungeon.transition.take() {
+        dungeon.apply(transition, &mut map);
+        return;
+    }
+
+    let new_map = match *source {
+        MapSource::Generated(terrain) => Map::generate_map(seed.value, terrain),
+        MapSource::Authored | MapSource::Tiled => {
+            let handle = match *source {
+                MapSource::Tiled => &map_assets.tiled,
+                _ => &map_assets.story,
+            };
+            match world_maps.get(handle) {
+                Some(world_map) => Map::from_world_map(seed.value, *source, world_map.clone()),
+                None => {
+                    error!(
+                        "{} world map is not available, generating one instead",
+                        *source
+                    );
+                    Map::generate_map(seed.value, TerrainKind::default())
+                }
+            }
+        }
+    };
+    *map = new_map;
 }
 
 fn spawn_map(mut commands: Commands, map: Res<Map>, texture_atlas: Res<TileMapAtlas>) {
@@ -317,15 +645,18 @@ fn spawn_map(mut commands: Commands, map: Res<Map>, texture_atlas: Res<TileMapAt
         .unwrap();
 
     // ワールドに追加
-    tilemap_components.tilemap.spawn_chunk((-1, 0)).unwrap();
+    // dungeon floors do not wrap, so only the center chunk is shown
+    if map.wrap {
+        tilemap_components.tilemap.spawn_chunk((-1, 0)).unwrap();
+        tilemap_components.tilemap.spawn_chunk((1, 0)).unwrap();
+        tilemap_components.tilemap.spawn_chunk((-1, 1)).unwrap();
+        tilemap_components.tilemap.spawn_chunk((0, 1)).unwrap();
+        tilemap_components.tilemap.spawn_chunk((1, 1)).unwrap();
+        tilemap_components.tilemap.spawn_chunk((-1, -1)).unwrap();
+        tilemap_components.tilemap.spawn_chunk((0, -1)).unwrap();
+        tilemap_components.tilemap.spawn_chunk((1, -1)).unwrap();
+    }
     tilemap_components.tilemap.spawn_chunk((0, 0)).unwrap();
-    tilemap_components.tilemap.spawn_chunk((1, 0)).unwrap();
-    tilemap_components.tilemap.spawn_chunk((-1, 1)).unwrap();
-    tilemap_components.tilemap.spawn_chunk((0, 1)).unwrap();
-    tilemap_components.tilemap.spawn_chunk((1, 1)).unwrap();
-    tilemap_components.tilemap.spawn_chunk((-1, -1)).unwrap();
-    tilemap_components.tilemap.spawn_chunk((0, -1)).unwrap();
-    tilemap_components.tilemap.spawn_chunk((1, -1)).unwrap();
 
     commands.spawn_bundle(tilemap_components).insert(TileMap);
 }
@@ -412,8 +743,7 @@ fn animate_mini_map(
     }
 }
 
-fn rng_multi_range(range1: (f32, f32), range2: (f32, f32)) -> f32 {
-    let mut rng = rand::thread_rng();
+fn rng_multi_range(rng: &mut impl Rng, range1: (f32, f32), range2: (f32, f32)) -> f32 {
     if rng.gen_bool(0.5) {
         rng.gen_range(range1.0..range1.1)
     } else {
@@ -426,3 +756,77 @@ fn clean_up_all_tilemap(mut commands: Commands, mut tilemap: Query<(Entity, &Til
         commands.entity(entity).despawn_recursive();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn towns(map: &Map) -> Vec<((i32, i32), Field)> {
+        let mut towns: Vec<((i32, i32), Field)> = map
+            .fields
+            .iter()
+            .filter(|(_pos, field)| matches!(field, Field::Town { .. } | Field::Castle))
+            .map(|(pos, field)| (*pos, field.clone()))
+            .collect();
+        towns.sort_by_key(|(pos, _field)| *pos);
+        towns
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_world() {
+        for terrain in [TerrainKind::Scatter, TerrainKind::Noise].iter() {
+            let map = Map::generate_map(1234, *terrain);
+            let again = Map::generate_map(1234, *terrain);
+            assert_eq!(map.fields, again.fields);
+            assert_eq!(map.collisions, again.collisions);
+            assert_eq!(towns(&map), towns(&again));
+            assert_ne!(map.fields, Map::generate_map(1235, *terrain).fields);
+        }
+    }
+
+    #[test]
+    fn every_town_and_the_castle_can_be_walked_to() {
+        for seed in 0..1000 {
+            for terrain in [TerrainKind::Scatter, TerrainKind::Noise].iter() {
+                let map = Map::generate_map(seed, *terrain);
+                assert!(!map.fields[&map.start].is_obstacle());
+                assert_eq!(
+                    unreachable_fields(&map.fields, map.start),
+                    vec![],
+                    "seed {} {}",
+                    seed,
+                    terrain
+                );
+                assert_eq!(
+                    towns(&map)
+                        .iter()
+                        .filter(|(_pos, field)| *field == Field::Castle)
+                        .count(),
+                    1
+                );
+            }
+        }
+    }
+
+    // ChaCha8 gives these on every platform, a change here changes every shared seed
+    #[test]
+    fn seed_42_is_stable() {
+        let map = Map::generate_map(42, TerrainKind::Scatter);
+        assert_eq!(map.fields[&(21, -15)], Field::Castle);
+        assert_eq!(
+            map.fields[&(-22, 14)],
+            Field::Town {
+                item: Item::SpellHeal(1),
+                visited: false
+            }
+        );
+        assert_eq!(map.fields[&(-20, 5)], Field::Mountain);
+        assert_eq!(map.fields[&(-7, -3)], Field::Grass);
+        assert_eq!(towns(&map).len(), 21);
+
+        let map = Map::generate_map(42, TerrainKind::Noise);
+        assert_eq!(map.fields[&(-26, 16)], Field::Castle);
+        assert_eq!(map.fields[&(10, -10)], Field::Mountain);
+        assert_eq!(map.fields[&(-20, 5)], Field::Forest);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::loading::FontAssets;
-use crate::map::TileMap;
+use crate::map::{MapSource, TileMap, WorldSeed};
 use crate::player::Player;
 use crate::setup::ForState;
 use crate::AppState;
@@ -12,11 +12,19 @@ pub struct MenuPlugin;
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system_set(SystemSet::on_enter(AppState::Menu).with_system(setup_menu.system()))
-            .add_system_set(SystemSet::on_update(AppState::Menu).with_system(update_menu.system()));
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(update_menu.system())
+                    .with_system(input_seed.system())
+                    .with_system(update_seed_text.system()),
+            );
     }
 }
 
 pub struct UiTitleText;
+pub struct UiSeedText;
+
+const MAX_SEED_DIGITS: u64 = 1_000_000_000_000_000;
 
 fn setup_menu(
     mut commands: Commands,
@@ -24,6 +32,8 @@ fn setup_menu(
     mut materials: ResMut<Assets<ColorMaterial>>,
     player: Query<Entity, With<Player>>,
     tilemap: Query<Entity, With<TileMap>>,
+    mut seed: ResMut<WorldSeed>,
+    source: Res<MapSource>,
 ) {
     // Initialize for restart play
     for entity in player.iter() {
@@ -32,6 +42,10 @@ fn setup_menu(
     for entity in tilemap.iter() {
         commands.entity(entity).despawn_recursive();
     }
+    // Roll a new world unless the player asked for a specific one
+    if !seed.requested {
+        *seed = WorldSeed::random();
+    }
 
     commands
         .spawn_bundle(NodeBundle {
@@ -127,6 +141,66 @@ fn setup_menu(
                         .insert(Timer::from_seconds(1., true))
                         .insert(UiTitleText);
                 });
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.)),
+                        align_self: AlignSelf::Center,
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        "Press 'C' for saved games",
+                        TextStyle {
+                            font: font_assets.pixel_mplus.clone(),
+                            font_size: 40.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ForState {
+                    states: vec![AppState::Menu],
+                });
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.), Val::Percent(15.0)),
+                        margin: Rect::all(Val::Px(20.0)),
+                        justify_content: JustifyContent::Center,
+                        align_self: AlignSelf::Center,
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::BLACK.into()),
+                    ..Default::default()
+                })
+                .insert(ForState {
+                    states: vec![AppState::Menu],
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                margin: Rect::all(Val::Px(5.)),
+                                align_self: AlignSelf::Center,
+                                ..Default::default()
+                            },
+                            text: Text::with_section(
+                                seed_text(&seed, &source),
+                                TextStyle {
+                                    font: font_assets.pixel_mplus.clone(),
+                                    font_size: 40.0,
+                                    color: Color::WHITE,
+                                },
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(ForState {
+                            states: vec![AppState::Menu],
+                        })
+                        .insert(UiSeedText);
+                });
         });
 }
 
@@ -142,3 +216,52 @@ fn update_menu(time: Res<Time>, mut query: Query<(&mut Timer, &mut Text), With<U
         }
     }
 }
+
+fn seed_text(seed: &WorldSeed, source: &MapSource) -> String {
+    format!(
+        "Seed: {}  (type digits to change)\nWorld: {}  (Tab to change)",
+        seed.value, source
+    )
+}
+
+// Digits typed on the title screen replace the random seed, Tab switches the world map source
+fn input_seed(
+    mut char_events: EventReader<ReceivedCharacter>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut seed: ResMut<WorldSeed>,
+    mut source: ResMut<MapSource>,
+) {
+    for event in char_events.iter() {
+        if let Some(digit) = event.char.to_digit(10) {
+            if !seed.requested {
+                *seed = WorldSeed {
+                    value: digit as u64,
+                    requested: true,
+                };
+            } else if seed.value < MAX_SEED_DIGITS {
+                seed.value = seed.value * 10 + digit as u64;
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        seed.value /= 10;
+        seed.requested = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        *source = source.next();
+        keyboard_input.reset(KeyCode::Tab);
+    }
+}
+
+fn update_seed_text(
+    seed: Res<WorldSeed>,
+    source: Res<MapSource>,
+    mut query: Query<&mut Text, With<UiSeedText>>,
+) {
+    if !seed.is_changed() && !source.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = seed_text(&seed, &source);
+    }
+}
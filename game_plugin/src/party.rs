@@ -0,0 +1,141 @@
+use crate::character_status::{CharacterStatus, Skill};
+use crate::growth::Growth;
+use crate::inventory::{Inventory, Item};
+use crate::player::Player;
+use crate::AppState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::array::IntoIter;
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+pub struct PartyPlugin;
+
+// This plugin keeps the heroes travelling with the player.
+impl Plugin for PartyPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<RecruitData>().add_system_set(
+            SystemSet::on_enter(AppState::Menu).with_system(clean_up_party.system()),
+        );
+    }
+}
+
+pub const PARTY_SIZE: usize = 4;
+
+// Every hero of the party has one. The player's own hero is 0 and the others
+// are entities without a sprite that follow the player around.
+#[derive(Clone, Copy, Debug)]
+pub struct PartyMember {
+    pub order: usize,
+}
+
+// A hero as kept in saves
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MemberData {
+    pub status: CharacterStatus,
+    pub inventory: Inventory,
+}
+
+pub struct Recruit {
+    pub name: &'static str,
+    pub skills: Vec<Skill>,
+}
+
+impl Recruit {
+    // Joins at the leader's level and takes the treasure of the town
+    pub fn join(&self, leader: &CharacterStatus, item: Item, growth: &Growth) -> MemberData {
+        let mut inventory = Inventory {
+            skills: self.skills.clone(),
+            learned: self.skills.clone(),
+            ..Default::default()
+        };
+        inventory.add_item(item);
+        let mut status = CharacterStatus {
+            name: self.name.to_string(),
+            exp: leader.exp,
+            ..Default::default()
+        };
+        status.level_up(leader.lv, growth, &inventory);
+        status.heal2max();
+        // 途中のレベルで覚えるはずだった呪文も覚えている
+        for spell in growth.learned(self.name, 0, leader.lv) {
+            inventory.learn(spell);
+        }
+        MemberData { status, inventory }
+    }
+}
+
+// Heroes waiting in the towns holding these treasures
+pub struct RecruitData {
+    pub recruits: HashMap<Item, Recruit>,
+}
+impl Default for RecruitData {
+    fn default() -> Self {
+        RecruitData {
+            recruits: HashMap::<_, _>::from_iter(IntoIter::new([
+                (
+                    Item::IronBody,
+                    Recruit {
+                        name: "Knight",
+                        skills: vec![Skill::Sword],
+                    },
+                ),
+                (
+                    Item::WisdomRing,
+                    Recruit {
+                        name: "Mage",
+                        skills: vec![Skill::Sword, Skill::Spell(Item::SpellFire(1))],
+                    },
+                ),
+                (
+                    Item::FairyShield,
+                    Recruit {
+                        name: "Priest",
+                        skills: vec![
+                            Skill::Sword,
+                            Skill::Spell(Item::SpellHeal(1)),
+                            Skill::Spell(Item::SpellCure(1)),
+                        ],
+                    },
+                ),
+            ])),
+        }
+    }
+}
+
+impl RecruitData {
+    pub fn recruit(&self, item: &Item) -> Option<&Recruit> {
+        self.recruits.get(item)
+    }
+}
+
+pub fn spawn_member(commands: &mut Commands, order: usize, member: MemberData) -> Entity {
+    commands
+        .spawn()
+        .insert(PartyMember { order })
+        .insert(member.status)
+        .insert(member.inventory)
+        .id()
+}
+
+// Orders of the members that can still fight, from the leader down
+pub fn alive_members<'a>(
+    members: impl Iterator<Item = (&'a CharacterStatus, &'a PartyMember)>,
+) -> Vec<usize> {
+    let mut alive: Vec<usize> = members
+        .filter(|(status, _)| status.hp_current > 0)
+        .map(|(_, member)| member.order)
+        .collect();
+    alive.sort_unstable();
+    alive
+}
+
+// The player's hero is despawned by the player plugin
+fn clean_up_party(
+    mut commands: Commands,
+    query: Query<Entity, (With<PartyMember>, Without<Player>)>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
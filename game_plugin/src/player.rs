@@ -1,11 +1,16 @@
 use crate::actions::Action;
 use crate::character_status::CharacterStatus;
+use crate::dungeon::DungeonTransition;
 use crate::enemies::EnemyData;
 use crate::events::GameEvent;
+use crate::growth::Growth;
 use crate::inventory::Inventory;
 use crate::loading::PlayerAtlas;
 use crate::map::{Field, Map, MiniMap, Position, MAP_SIZE};
+use crate::party::{spawn_member, MemberData, PartyMember};
+use crate::save::PendingLoad;
 use crate::setup::{render_layer, MapCamera, MapCameraState, RenderLayer};
+use crate::shop::STARTING_GOLD;
 use crate::AppState;
 use bevy::prelude::*;
 use bevy::render::camera::RenderLayers;
@@ -39,8 +44,11 @@ pub enum PlayerMovement {
 #[derive(Debug)]
 pub enum PlayerBattleState {
     Select,
+    // choosing which enemy to attack
+    Target,
     Attack,
-    Defense,
+    // the next combatant in the turn order acts
+    NextTurn,
 }
 
 pub struct Player {
@@ -54,17 +62,49 @@ fn spawn_player(
     texture_atlas: Res<PlayerAtlas>,
     mut camera_query: Query<(Entity, &mut Transform, &mut Position, &mut MapCamera)>,
     mut app_state: ResMut<State<AppState>>,
+    mut pending_load: ResMut<PendingLoad>,
+    growth: Res<Growth>,
 ) {
-    // Delete player for second play
-    for entity in player.iter() {
-        commands.entity(entity).despawn_recursive();
+    let new_game = pending_load.save.is_none();
+    let mut party = match pending_load.save.take() {
+        Some(save) => save.party,
+        None => vec![MemberData {
+            status: CharacterStatus::default(),
+            inventory: Inventory {
+                gold: STARTING_GOLD,
+                ..Default::default()
+            },
+        }],
+    };
+    // 能力値はレベルと装備から求め直す。覚えるはずの呪文もここで覚える
+    for member in party.iter_mut() {
+        member
+            .status
+            .update_stats(&growth, &member.inventory.equipment);
+        for spell in growth.learned(&member.status.name, 0, member.status.lv) {
+            member.inventory.learn(spell);
+        }
+        if new_game {
+            member.status.heal2max();
+        }
     }
 
     for (camera, mut transform, mut position, mut map_camera) in camera_query.iter_mut() {
-        *transform =
-            map.position_to_translation(&Position { x: 0., y: 0. }, transform.translation.z);
-        *position = Position { x: 0., y: 0. };
-        *map_camera = MapCamera::default();
+        let start = Position {
+            x: map.start.0 as f32,
+            y: map.start.1 as f32,
+        };
+        *transform = map.position_to_translation(&start, transform.translation.z);
+        *position = start;
+        *map_camera = MapCamera {
+            destination: start,
+            ..Default::default()
+        };
+
+        // Keep the player when moving between the world map and dungeon floors
+        if player.iter().next().is_some() {
+            continue;
+        }
 
         let player = commands
             .spawn_bundle(SpriteSheetBundle {
@@ -80,12 +120,16 @@ fn spawn_player(
             .insert(Player {
                 battle_state: PlayerBattleState::Select,
             })
-            .insert(CharacterStatus::default())
-            .insert(Inventory::default())
+            .insert(PartyMember { order: 0 })
+            .insert(party[0].status.clone())
+            .insert(party[0].inventory.clone())
             // .insert(position)
             .insert(Timer::from_seconds(0.5, true))
             .id();
         commands.entity(camera).push_children(&[player]);
+        for (order, member) in party.drain(..).enumerate().skip(1) {
+            spawn_member(&mut commands, order, member);
+        }
     }
 
     app_state.set(AppState::InGameExplore).unwrap();
@@ -112,6 +156,7 @@ fn move_player(
     map: Res<Map>,
     enemy_data: Res<EnemyData>,
     mut mini_tilemap_query: Query<&mut Tilemap, With<MiniMap>>,
+    mut party_query: Query<&mut CharacterStatus, With<PartyMember>>,
 ) {
     if let Some((mut transform, mut position, mut map_camera)) = map_camera_query.iter_mut().next()
     {
@@ -119,22 +164,36 @@ fn move_player(
         if map_camera.destination == *position {
             if matches!(map_camera.state, MapCameraState::Moving) {
                 map_camera.state = MapCameraState::Stop;
+                // 毒は一歩ごとに体力を奪う
+                for mut status in party_query.iter_mut() {
+                    status.poison_step();
+                }
 
                 let field = map.position_to_field(&position);
                 match field {
                     Field::Town { item, visited } => {
                         events_writer.send(GameEvent::TownArrived(item, visited))
                     }
-                    Field::Castle => {
-                        let enemy = enemy_data.field_to_enemy(&map.position_to_field(&position));
-                        events_writer.send(GameEvent::EnemyEncountered(enemy))
+                    Field::Castle => events_writer.send(GameEvent::DungeonEntered),
+                    Field::StairsUp => {
+                        events_writer.send(GameEvent::FloorChanged(DungeonTransition::Up))
+                    }
+                    Field::StairsDown => {
+                        events_writer.send(GameEvent::FloorChanged(DungeonTransition::Down))
+                    }
+                    Field::Throne => {
+                        let group = enemy_data.field_to_group(&map.position_to_field(&position));
+                        events_writer.send(GameEvent::EnemyEncountered(group))
                     }
-                    Field::Grass | Field::Forest | Field::Mountain => {
-                        let field = map.position_to_field(&position);
+                    Field::Grass | Field::Forest | Field::Mountain | Field::Floor => {
+                        let field = map.encounter_field(&position);
+                        let rate = map
+                            .encounter_rate(&position)
+                            .unwrap_or_else(|| enemy_data.field_to_rate(&field));
                         let mut rng = rand::thread_rng();
-                        if rng.gen_bool((1. / enemy_data.field_to_rate(&field) as f32) as f64) {
-                            let enemy = enemy_data.field_to_enemy(&field);
-                            events_writer.send(GameEvent::EnemyEncountered(enemy));
+                        if rate > 0 && rng.gen_bool((1. / rate as f32) as f64) {
+                            let group = enemy_data.field_to_group(&field);
+                            events_writer.send(GameEvent::EnemyEncountered(group));
                         }
                     }
                     _ => {}
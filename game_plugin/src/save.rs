@@ -0,0 +1,261 @@
+use crate::map::{EncounterZone, Field, Map, MapSource, Position};
+use crate::party::MemberData;
+use crate::save_backend::{default_backend, SaveBackend};
+use crate::save_migrations::migrate;
+use crate::world_map::WorldMapAsset;
+use crate::AppState;
+use anyhow::anyhow;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub struct SavePlugin;
+
+// This plugin keeps the save slots and the play time of the current run.
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SaveStorage>()
+            .init_resource::<PendingLoad>()
+            .init_resource::<PlayTime>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Menu).with_system(reset_play_time.system()),
+            )
+            .add_system(count_play_time.system());
+    }
+}
+
+pub const SAVE_SLOTS: usize = 3;
+
+// Bump when the layout of SaveData changes and add a migration in save_migrations.rs
+pub const SAVE_VERSION: u32 = 10;
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    // seconds since the unix epoch
+    pub saved_at: u64,
+    // seconds
+    pub play_time: f64,
+    pub map: SavedMap,
+    pub position: (i32, i32),
+    // the player's hero first
+    pub party: Vec<MemberData>,
+}
+
+// Everything needed to rebuild the world map, including visited towns
+#[derive(Serialize, Deserialize)]
+pub struct SavedMap {
+    pub seed: u64,
+    pub source: MapSource,
+    #[serde(with = "pairs")]
+    pub fields: HashMap<(i32, i32), Field>,
+    #[serde(with = "pairs")]
+    pub background: HashMap<(i32, i32), usize>,
+    #[serde(with = "pairs")]
+    pub foreground: HashMap<(i32, i32), usize>,
+    #[serde(with = "pairs")]
+    pub encounter_zones: HashMap<(i32, i32), EncounterZone>,
+}
+
+impl SaveData {
+    pub fn new(
+        map: &Map,
+        position: &Position,
+        party: Vec<MemberData>,
+        play_time: &PlayTime,
+    ) -> Self {
+        let world_map = map.to_world_map();
+        SaveData {
+            version: SAVE_VERSION,
+            saved_at: now_unix_seconds(),
+            play_time: play_time.seconds,
+            map: SavedMap {
+                seed: map.seed,
+                source: map.source,
+                fields: world_map.fields,
+                background: world_map.background,
+                foreground: world_map.foreground,
+                encounter_zones: world_map.encounter_zones,
+            },
+            position: (position.x as i32, position.y as i32),
+            party,
+        }
+    }
+
+    // The player starts where the game was saved
+    pub fn to_map(&self) -> Map {
+        Map::from_world_map(
+            self.map.seed,
+            self.map.source,
+            WorldMapAsset {
+                fields: self.map.fields.clone(),
+                start: self.position,
+                background: self.map.background.clone(),
+                foreground: self.map.foreground.clone(),
+                encounter_zones: self.map.encounter_zones.clone(),
+            },
+        )
+    }
+
+    // (visited, all)
+    pub fn towns_visited(&self) -> (usize, usize) {
+        self.map
+            .fields
+            .values()
+            .fold((0, 0), |(visited, all), field| match field {
+                Field::Town { visited: true, .. } => (visited + 1, all + 1),
+                Field::Town { .. } => (visited, all + 1),
+                _ => (visited, all),
+            })
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(text: &str) -> anyhow::Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(text).map_err(|err| anyhow!("the save is corrupt: {}", err))?;
+        let value = migrate(value)?;
+        serde_json::from_value(value).map_err(|err| anyhow!("the save is corrupt: {}", err))
+    }
+}
+
+// Set when a slot is loaded and consumed while the map scene starts
+#[derive(Default)]
+pub struct PendingLoad {
+    pub save: Option<SaveData>,
+}
+
+pub struct SaveStorage {
+    backend: Box<dyn SaveBackend>,
+}
+impl Default for SaveStorage {
+    fn default() -> Self {
+        SaveStorage {
+            backend: default_backend(),
+        }
+    }
+}
+
+impl SaveStorage {
+    // Ok(None) for an empty slot, Err for a corrupt or incompatible one
+    pub fn load(&self, slot: usize) -> anyhow::Result<Option<SaveData>> {
+        match self.backend.read(slot)? {
+            Some(text) => SaveData::from_json(&text).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, slot: usize, save: &SaveData) -> anyhow::Result<()> {
+        self.backend.write(slot, &save.to_json()?)
+    }
+
+    pub fn copy(&self, from: usize, to: usize) -> anyhow::Result<()> {
+        match self.load(from)? {
+            Some(save) => self.save(to, &save),
+            None => Err(anyhow!("slot {} is empty", from + 1)),
+        }
+    }
+
+    pub fn delete(&self, slot: usize) -> anyhow::Result<()> {
+        self.backend.delete(slot)
+    }
+}
+
+// Time spent in the game scenes of the current run
+#[derive(Default)]
+pub struct PlayTime {
+    pub seconds: f64,
+}
+
+fn reset_play_time(mut play_time: ResMut<PlayTime>) {
+    play_time.seconds = 0.;
+}
+
+fn count_play_time(time: Res<Time>, state: Res<State<AppState>>, mut play_time: ResMut<PlayTime>) {
+    if matches!(
+        state.current(),
+        AppState::InGameMap
+            | AppState::InGameExplore
+            | AppState::InGameBattle
+            | AppState::InGameEvent
+    ) {
+        play_time.seconds += time.delta_seconds_f64();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_unix_seconds() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// std::time::SystemTime is not available in the browser
+#[cfg(target_arch = "wasm32")]
+fn now_unix_seconds() -> u64 {
+    (js_sys::Date::now() / 1000.) as u64
+}
+
+// e.g. "1:02:03"
+pub fn format_play_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// e.g. "2021-06-01 12:34 UTC"
+pub fn format_timestamp(unix_seconds: u64) -> String {
+    let days = (unix_seconds / 86400) as i64;
+    let minutes = unix_seconds % 86400 / 60;
+    // days to civil date, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+// JSON objects only have string keys, so maps keyed by positions are stored as pairs
+mod pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+    use std::hash::Hash;
+
+    pub fn serialize<S, K, V>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        K: Serialize + Eq + Hash,
+        V: Serialize,
+    {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, D, K, V>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+    {
+        Vec::<(K, V)>::deserialize(deserializer).map(|pairs| pairs.into_iter().collect())
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::anyhow;
+
+// Where serialized saves are kept. The native build writes files,
+// the web build has no filesystem and uses the browser's localStorage.
+pub trait SaveBackend: Send + Sync {
+    // None when nothing has been saved in the slot yet
+    fn read(&self, slot: usize) -> anyhow::Result<Option<String>>;
+    fn write(&self, slot: usize, text: &str) -> anyhow::Result<()>;
+    fn delete(&self, slot: usize) -> anyhow::Result<()>;
+}
+
+#[cfg(feature = "native")]
+pub fn default_backend() -> Box<dyn SaveBackend> {
+    Box::new(FileBackend)
+}
+
+#[cfg(all(feature = "web", not(feature = "native")))]
+pub fn default_backend() -> Box<dyn SaveBackend> {
+    Box::new(LocalStorageBackend)
+}
+
+#[cfg(not(any(feature = "native", feature = "web")))]
+pub fn default_backend() -> Box<dyn SaveBackend> {
+    Box::new(NoBackend)
+}
+
+// `slot1.json`, `slot2.json`, ... in the platform data directory
+#[cfg(feature = "native")]
+pub struct FileBackend;
+
+#[cfg(feature = "native")]
+impl FileBackend {
+    fn path(&self, slot: usize) -> anyhow::Result<std::path::PathBuf> {
+        let dir = dirs::data_dir().ok_or_else(|| anyhow!("no data directory on this platform"))?;
+        Ok(dir
+            .join("dq-like-rpg")
+            .join(format!("slot{}.json", slot + 1)))
+    }
+}
+
+#[cfg(feature = "native")]
+impl SaveBackend for FileBackend {
+    fn read(&self, slot: usize) -> anyhow::Result<Option<String>> {
+        use anyhow::Context;
+        let path = self.path(slot)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        std::fs::read_to_string(&path)
+            .map(Some)
+            .with_context(|| format!("failed to read {}", path.display()))
+    }
+
+    fn write(&self, slot: usize, text: &str) -> anyhow::Result<()> {
+        use anyhow::Context;
+        let path = self.path(slot)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        std::fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn delete(&self, slot: usize) -> anyhow::Result<()> {
+        use anyhow::Context;
+        let path = self.path(slot)?;
+        if !path.exists() {
+            return Ok(());
+        }
+        std::fs::remove_file(&path).with_context(|| format!("failed to delete {}", path.display()))
+    }
+}
+
+// `dq-like-rpg.slot1`, `dq-like-rpg.slot2`, ... entries of window.localStorage
+#[cfg(feature = "web")]
+pub struct LocalStorageBackend;
+
+#[cfg(feature = "web")]
+impl LocalStorageBackend {
+    fn key(&self, slot: usize) -> String {
+        format!("dq-like-rpg.slot{}", slot + 1)
+    }
+
+    fn storage(&self) -> anyhow::Result<web_sys::Storage> {
+        web_sys::window()
+            .ok_or_else(|| anyhow!("no browser window"))?
+            .local_storage()
+            .map_err(|err| anyhow!("localStorage is not accessible: {:?}", err))?
+            .ok_or_else(|| anyhow!("localStorage is not available in this browser"))
+    }
+}
+
+#[cfg(feature = "web")]
+impl SaveBackend for LocalStorageBackend {
+    fn read(&self, slot: usize) -> anyhow::Result<Option<String>> {
+        self.storage()?
+            .get_item(&self.key(slot))
+            .map_err(|err| anyhow!("failed to read localStorage: {:?}", err))
+    }
+
+    fn write(&self, slot: usize, text: &str) -> anyhow::Result<()> {
+        self.storage()?
+            .set_item(&self.key(slot), text)
+            .map_err(|err| anyhow!("failed to write localStorage: {:?}", err))
+    }
+
+    fn delete(&self, slot: usize) -> anyhow::Result<()> {
+        self.storage()?
+            .remove_item(&self.key(slot))
+            .map_err(|err| anyhow!("failed to delete from localStorage: {:?}", err))
+    }
+}
+
+// Built without the `native` or `web` feature
+#[cfg(not(any(feature = "native", feature = "web")))]
+pub struct NoBackend;
+
+#[cfg(not(any(feature = "native", feature = "web")))]
+impl SaveBackend for NoBackend {
+    fn read(&self, _slot: usize) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn write(&self, _slot: usize, _text: &str) -> anyhow::Result<()> {
+        Err(anyhow!("saving needs the `native` or `web` feature"))
+    }
+
+    fn delete(&self, _slot: usize) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,577 @@
+use crate::save::SAVE_VERSION;
+use anyhow::anyhow;
+use serde_json::{json, Map, Value};
+
+// MIGRATIONS[i] upgrades a save of version i + 1 to version i + 2.
+// Every released version keeps a fixture in `fixtures/saves/` that must still load.
+const MIGRATIONS: &[fn(Value) -> anyhow::Result<Value>] = &[
+    v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8, v8_to_v9, v9_to_v10,
+];
+
+// Upgrade a save of any older version to SAVE_VERSION
+pub fn migrate(mut value: Value) -> anyhow::Result<Value> {
+    let version = version_of(&value)?;
+    if version > SAVE_VERSION {
+        return Err(anyhow!(
+            "the save is from a newer version of the game (save version {}, this game reads up to {})",
+            version,
+            SAVE_VERSION
+        ));
+    }
+    for migration in MIGRATIONS[(version - 1) as usize..].iter() {
+        value = migration(value)?;
+    }
+    Ok(value)
+}
+
+fn version_of(value: &Value) -> anyhow::Result<u32> {
+    match value.get("version").and_then(|version| version.as_u64()) {
+        Some(version) if (1..=u32::MAX as u64).contains(&version) => Ok(version as u32),
+        Some(version) => Err(anyhow!("the save has an invalid version {}", version)),
+        None => Err(anyhow!("the save has no version")),
+    }
+}
+
+fn object(value: &mut Value) -> anyhow::Result<&mut Map<String, Value>> {
+    value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("the save is corrupt: expected an object"))
+}
+
+// Version 2 added the timestamp and play time shown on the slot screen
+fn v1_to_v2(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    save.insert("version".to_string(), json!(2));
+    save.insert("saved_at".to_string(), json!(0));
+    save.insert("play_time".to_string(), json!(0.0));
+    Ok(value)
+}
+
+// Version 3 replaced the single hero with a party, the hero of older saves travels alone
+fn v2_to_v3(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    let status = save
+        .remove("status")
+        .ok_or_else(|| anyhow!("the save is corrupt: missing status"))?;
+    let inventory = save
+        .remove("inventory")
+        .ok_or_else(|| anyhow!("the save is corrupt: missing inventory"))?;
+    save.insert("version".to_string(), json!(3));
+    save.insert(
+        "party".to_string(),
+        json!([{"status": status, "inventory": inventory}]),
+    );
+    Ok(value)
+}
+
+// Version 4 added speed, older heroes get the speed of their level
+fn v3_to_v4(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    save.insert("version".to_string(), json!(4));
+    let party = save
+        .get_mut("party")
+        .and_then(|party| party.as_array_mut())
+        .ok_or_else(|| anyhow!("the save is corrupt: missing party"))?;
+    for member in party.iter_mut() {
+        let status = member
+            .get_mut("status")
+            .and_then(|status| status.as_object_mut())
+            .ok_or_else(|| anyhow!("the save is corrupt: missing status"))?;
+        let lv = status.get("lv").and_then(|lv| lv.as_i64()).unwrap_or(1);
+        status.insert(
+            "speed".to_string(),
+            json!((10 + (lv - 1) * 3).clamp(1, 999)),
+        );
+    }
+    Ok(value)
+}
+
+// Version 5 added status ailments, nobody in older saves suffers from one
+fn v4_to_v5(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    save.insert("version".to_string(), json!(5));
+    let party = save
+        .get_mut("party")
+        .and_then(|party| party.as_array_mut())
+        .ok_or_else(|| anyhow!("the save is corrupt: missing party"))?;
+    for member in party.iter_mut() {
+        let status = member
+            .get_mut("status")
+            .and_then(|status| status.as_object_mut())
+            .ok_or_else(|| anyhow!("the save is corrupt: missing status"))?;
+        status.insert("ailments".to_string(), json!([]));
+    }
+    Ok(value)
+}
+
+// Version 6 added accuracy, evasion and critical rate, the hero sword raises the critical rate
+fn v5_to_v6(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    save.insert("version".to_string(), json!(6));
+    let party = save
+        .get_mut("party")
+        .and_then(|party| party.as_array_mut())
+        .ok_or_else(|| anyhow!("the save is corrupt: missing party"))?;
+    for member in party.iter_mut() {
+        let hero_sword = member
+            .pointer("/inventory/items")
+            .and_then(|items| items.as_array())
+            .map_or(false, |items| items.contains(&json!("HeroSword")));
+        let status = member
+            .get_mut("status")
+            .and_then(|status| status.as_object_mut())
+            .ok_or_else(|| anyhow!("the save is corrupt: missing status"))?;
+        status.insert("accuracy".to_string(), json!(95));
+        status.insert("evasion".to_string(), json!(5));
+        status.insert(
+            "critical".to_string(),
+            json!(if hero_sword { 25 } else { 5 }),
+        );
+    }
+    Ok(value)
+}
+
+// Version 7 added gold, older heroes start with the gold of a new game
+fn v6_to_v7(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    save.insert("version".to_string(), json!(7));
+    let party = save
+        .get_mut("party")
+        .and_then(|party| party.as_array_mut())
+        .ok_or_else(|| anyhow!("the save is corrupt: missing party"))?;
+    for (order, member) in party.iter_mut().enumerate() {
+        let inventory = member
+            .get_mut("inventory")
+            .and_then(|inventory| inventory.as_object_mut())
+            .ok_or_else(|| anyhow!("the save is corrupt: missing inventory"))?;
+        // only the player's hero carries gold
+        inventory.insert("gold".to_string(), json!(if order == 0 { 50 } else { 0 }));
+    }
+    Ok(value)
+}
+
+// Version 8 keeps consumables in stacks, the antidote is no longer a skill
+fn v7_to_v8(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    save.insert("version".to_string(), json!(8));
+    let party = save
+        .get_mut("party")
+        .and_then(|party| party.as_array_mut())
+        .ok_or_else(|| anyhow!("the save is corrupt: missing party"))?;
+    for member in party.iter_mut() {
+        let inventory = member
+            .get_mut("inventory")
+            .and_then(|inventory| inventory.as_object_mut())
+            .ok_or_else(|| anyhow!("the save is corrupt: missing inventory"))?;
+        let mut antidotes = 0;
+        if let Some(items) = inventory
+            .get_mut("items")
+            .and_then(|items| items.as_array_mut())
+        {
+            let before = items.len();
+            items.retain(|item| *item != json!("Antidote"));
+            antidotes = before - items.len();
+        }
+        if let Some(skills) = inventory
+            .get_mut("skills")
+            .and_then(|skills| skills.as_array_mut())
+        {
+            skills.retain(|skill| *skill != json!({ "Spell": "Antidote" }));
+        }
+        let consumables = if antidotes > 0 {
+            json!([["Antidote", antidotes]])
+        } else {
+            json!([])
+        };
+        inventory.insert("consumables".to_string(), consumables);
+        inventory.insert("selected_skill_index".to_string(), json!(0));
+    }
+    Ok(value)
+}
+
+// Version 9 has equipment slots. Gear used to count just by being carried,
+// every piece of it had a slot of its own, so all of it is put on.
+fn v8_to_v9(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    save.insert("version".to_string(), json!(9));
+    let party = save
+        .get_mut("party")
+        .and_then(|party| party.as_array_mut())
+        .ok_or_else(|| anyhow!("the save is corrupt: missing party"))?;
+    for member in party.iter_mut() {
+        let inventory = member
+            .get_mut("inventory")
+            .and_then(|inventory| inventory.as_object_mut())
+            .ok_or_else(|| anyhow!("the save is corrupt: missing inventory"))?;
+        let mut equipment = json!({
+            "weapon": null,
+            "shield": null,
+            "head": null,
+            "body": null,
+            "arms": null,
+            "legs": null,
+            "accessory": null,
+        });
+        let items = inventory
+            .get("items")
+            .and_then(|items| items.as_array())
+            .cloned()
+            .unwrap_or_default();
+        for item in items {
+            let slot = match item.as_str() {
+                Some("HeroSword") => "weapon",
+                Some("FairyShield") => "shield",
+                Some("IronHead") => "head",
+                Some("IronBody") => "body",
+                Some("IronArm") => "arms",
+                Some("IronLeg") => "legs",
+                Some("WisdomRing") => "accessory",
+                _ => continue,
+            };
+            equipment[slot] = item;
+        }
+        inventory.insert("equipment".to_string(), equipment);
+    }
+    Ok(value)
+}
+
+// Version 10 remembers which skills a hero knows without their spell book.
+// Older saves only tell that of the spells whose book isn't carried.
+fn v9_to_v10(mut value: Value) -> anyhow::Result<Value> {
+    let save = object(&mut value)?;
+    save.insert("version".to_string(), json!(10));
+    let party = save
+        .get_mut("party")
+        .and_then(|party| party.as_array_mut())
+        .ok_or_else(|| anyhow!("the save is corrupt: missing party"))?;
+    for member in party.iter_mut() {
+        let inventory = member
+            .get_mut("inventory")
+            .and_then(|inventory| inventory.as_object_mut())
+            .ok_or_else(|| anyhow!("the save is corrupt: missing inventory"))?;
+        let items = inventory
+            .get("items")
+            .and_then(|items| items.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let learned: Vec<Value> = inventory
+            .get("skills")
+            .and_then(|skills| skills.as_array())
+            .map(|skills| {
+                skills
+                    .iter()
+                    .filter(|skill| {
+                        skill
+                            .get("Spell")
+                            .map_or(true, |spell| !items.contains(spell))
+                    })
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        inventory.insert("learned".to_string(), json!(learned));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character_status::{Ailment, CharacterStatus, Skill};
+    use crate::growth::{parse_growth, Growth};
+    use crate::inventory::Item;
+    use crate::map::{Field, MapSource};
+    use crate::save::SaveData;
+    use crate::terrain::TerrainKind;
+
+    // One fixture per version, all describing the same run
+    const FIXTURES: &[(u32, &str)] = &[
+        (1, include_str!("../fixtures/saves/v1.json")),
+        (2, include_str!("../fixtures/saves/v2.json")),
+        (3, include_str!("../fixtures/saves/v3.json")),
+        (4, include_str!("../fixtures/saves/v4.json")),
+        (5, include_str!("../fixtures/saves/v5.json")),
+        (6, include_str!("../fixtures/saves/v6.json")),
+        (7, include_str!("../fixtures/saves/v7.json")),
+        (8, include_str!("../fixtures/saves/v8.json")),
+        (9, include_str!("../fixtures/saves/v9.json")),
+        (10, include_str!("../fixtures/saves/v10.json")),
+    ];
+
+    fn load(version: u32, text: &str) -> SaveData {
+        SaveData::from_json(text).unwrap_or_else(|err| panic!("version {}: {:#}", version, err))
+    }
+
+    // The curves the heroes grew by before they became data
+    fn growth() -> Growth {
+        parse_growth(
+            r#"{"exp": [0, 10, 20, 40], "stats": {
+                "hp": {"base": 100, "rate": 25},
+                "mp": {"base": 100, "rate": 25},
+                "attack": {"base": 10, "rate": 5},
+                "defence": {"base": 10, "rate": 5},
+                "speed": {"base": 10, "rate": 3},
+                "accuracy": {"base": 95},
+                "evasion": {"base": 5},
+                "critical": {"base": 5}
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    // Stats aren't taken from the save, they are computed again when the party spawns
+    fn spawned(save: &SaveData) -> CharacterStatus {
+        let member = &save.party[0];
+        let mut status = member.status.clone();
+        status.update_stats(&growth(), &member.inventory.equipment);
+        status
+    }
+
+    #[test]
+    fn every_version_has_a_migration_and_a_fixture() {
+        assert_eq!(MIGRATIONS.len() as u32, SAVE_VERSION - 1);
+        let versions: Vec<u32> = FIXTURES.iter().map(|(version, _)| *version).collect();
+        assert_eq!(versions, (1..=SAVE_VERSION).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn fixtures_declare_their_version() {
+        for (version, text) in FIXTURES {
+            let value: Value = serde_json::from_str(text).unwrap();
+            assert_eq!(version_of(&value).unwrap(), *version);
+        }
+    }
+
+    #[test]
+    fn fixtures_load_as_the_current_version() {
+        for (version, text) in FIXTURES {
+            let save = load(*version, text);
+            assert_eq!(save.version, SAVE_VERSION, "version {}", version);
+            assert_eq!(save.map.seed, 1234);
+            assert_eq!(save.map.source, MapSource::Generated(TerrainKind::Noise));
+            assert_eq!(save.map.fields[&(10, 10)], Field::Castle);
+            assert_eq!(save.map.background[&(1, 0)], 2);
+            assert_eq!(save.map.foreground[&(0, 1)], 5);
+            assert_eq!(save.map.encounter_zones[&(1, 0)].rate, Some(6));
+            assert_eq!(save.towns_visited(), (1, 2));
+            assert_eq!(save.position, (2, -1));
+            assert_eq!(save.party[0].status.lv, 3);
+            assert_eq!(save.party[0].status.exp, 25);
+            // later versions carry more gear, the spell book is in every one
+            assert!(save.party[0].inventory.items.contains(&Item::SpellFire(1)));
+            assert_eq!(save.party[0].inventory.skills.len(), 2);
+        }
+    }
+
+    #[test]
+    fn v1_has_no_timestamp_or_play_time() {
+        let save = load(1, FIXTURES[0].1);
+        assert_eq!(save.saved_at, 0);
+        assert_eq!(save.play_time, 0.);
+    }
+
+    #[test]
+    fn v2_keeps_timestamp_and_play_time() {
+        let save = load(2, FIXTURES[1].1);
+        assert_eq!(save.saved_at, 1622550840);
+        assert_eq!(save.play_time, 754.5);
+    }
+
+    #[test]
+    fn v2_hero_travels_alone() {
+        let save = load(2, FIXTURES[1].1);
+        assert_eq!(save.party.len(), 1);
+        assert_eq!(save.party[0].status.name, "You");
+    }
+
+    #[test]
+    fn v3_heroes_get_the_speed_of_their_level() {
+        let save = load(3, FIXTURES[2].1);
+        assert_eq!(save.party[0].status.speed, 16);
+    }
+
+    #[test]
+    fn v4_keeps_speed() {
+        let save = load(4, FIXTURES[3].1);
+        assert_eq!(save.party[0].status.speed, 18);
+    }
+
+    #[test]
+    fn v4_heroes_are_healthy() {
+        let save = load(4, FIXTURES[3].1);
+        assert!(save.party[0].status.ailments.is_empty());
+    }
+
+    #[test]
+    fn v5_keeps_poison() {
+        let save = load(5, FIXTURES[4].1);
+        assert_eq!(save.party[0].status.ailments, vec![(Ailment::Poison, 3)]);
+    }
+
+    #[test]
+    fn v5_heroes_get_hit_rates() {
+        let save = load(5, FIXTURES[4].1);
+        assert_eq!(save.party[0].status.accuracy, 95);
+        assert_eq!(save.party[0].status.evasion, 5);
+        assert_eq!(save.party[0].status.critical, 5);
+    }
+
+    #[test]
+    fn v5_hero_sword_raises_critical_rate() {
+        let text =
+            FIXTURES[4]
+                .1
+                .replacen("\"items\": [", "\"items\": [\n          \"HeroSword\",", 1);
+        let save = load(5, &text);
+        assert_eq!(save.party[0].status.critical, 25);
+    }
+
+    #[test]
+    fn v6_hero_gets_starting_gold() {
+        let mut value: Value = serde_json::from_str(FIXTURES[5].1).unwrap();
+        let mage = value["party"][0].clone();
+        value["party"].as_array_mut().unwrap().push(mage);
+        let save = load(6, &value.to_string());
+        assert_eq!(save.party[0].inventory.gold, 50);
+        assert_eq!(save.party[1].inventory.gold, 0);
+    }
+
+    #[test]
+    fn v7_keeps_gold() {
+        let save = load(7, FIXTURES[6].1);
+        assert_eq!(save.party[0].inventory.gold, 120);
+    }
+
+    #[test]
+    fn v7_antidote_becomes_a_consumable() {
+        let text = FIXTURES[6]
+            .1
+            .replacen("\"items\": [", "\"items\": [\n          \"Antidote\",", 1)
+            .replacen(
+                "\"skills\": [",
+                "\"skills\": [\n          {\"Spell\": \"Antidote\"},",
+                1,
+            );
+        let save = load(7, &text);
+        let inventory = &save.party[0].inventory;
+        assert_eq!(inventory.count(&Item::Antidote), 1);
+        assert!(!inventory.items.contains(&Item::Antidote));
+        assert_eq!(inventory.skills.len(), 2);
+    }
+
+    #[test]
+    fn v8_keeps_consumables() {
+        let save = load(8, FIXTURES[7].1);
+        assert_eq!(save.party[0].inventory.consumables, vec![(Item::Herb, 3)]);
+    }
+
+    #[test]
+    fn v8_gear_is_put_on() {
+        let text = FIXTURES[7].1.replacen(
+            "\"items\": [",
+            "\"items\": [\n          \"IronBody\",\n          \"HeroSword\",",
+            1,
+        );
+        let save = load(8, &text);
+        let equipment = &save.party[0].inventory.equipment;
+        assert_eq!(equipment.body, Some(Item::IronBody));
+        assert_eq!(equipment.weapon, Some(Item::HeroSword));
+        assert_eq!(equipment.head, None);
+        assert_eq!(spawned(&save).critical, 25);
+    }
+
+    #[test]
+    fn v9_keeps_equipment() {
+        let save = load(9, FIXTURES[8].1);
+        let member = &save.party[0];
+        assert_eq!(member.inventory.equipment.weapon, Some(Item::IronSword));
+        assert_eq!(member.inventory.items.len(), 3);
+        // 保存された値ではなく装備から計算し直す
+        let status = spawned(&save);
+        assert_eq!(status.attack, 10 + (status.lv - 1) * 5 + 15);
+    }
+
+    #[test]
+    fn v9_spells_without_their_book_were_learned() {
+        let text = FIXTURES[8].1.replacen(
+            "\"skills\": [",
+            "\"skills\": [\n          {\"Spell\": {\"SpellHeal\": 1}},",
+            1,
+        );
+        let save = load(9, &text);
+        assert_eq!(
+            save.party[0].inventory.learned,
+            vec![Skill::Spell(Item::SpellHeal(1)), Skill::Sword]
+        );
+    }
+
+    #[test]
+    fn v10_keeps_learned_spells() {
+        let save = load(10, FIXTURES[9].1);
+        assert_eq!(
+            save.party[0].inventory.learned,
+            vec![Skill::Sword, Skill::Spell(Item::SpellFire(1))]
+        );
+    }
+
+    #[test]
+    fn party_round_trips() {
+        let (version, text) = FIXTURES[FIXTURES.len() - 1];
+        let mut save = load(version, text);
+        let mut mage = save.party[0].clone();
+        mage.status.name = "Mage".to_string();
+        save.party.push(mage);
+        let again = load(version, &save.to_json().unwrap());
+        assert_eq!(again.party.len(), 2);
+        assert_eq!(again.party[1].status.name, "Mage");
+    }
+
+    #[test]
+    fn current_version_round_trips() {
+        let (version, text) = FIXTURES[FIXTURES.len() - 1];
+        let save = load(version, text);
+        let again = load(version, &save.to_json().unwrap());
+        assert_eq!(again.map.fields, save.map.fields);
+        assert_eq!(again.saved_at, save.saved_at);
+        assert_eq!(again.party.len(), save.party.len());
+        assert_eq!(again.party[0].status.hp_max, save.party[0].status.hp_max);
+        assert_eq!(
+            again.party[0].status.ailments,
+            save.party[0].status.ailments
+        );
+        assert_eq!(again.party[0].inventory.gold, save.party[0].inventory.gold);
+        assert_eq!(
+            again.party[0].inventory.consumables,
+            save.party[0].inventory.consumables
+        );
+        assert_eq!(
+            again.party[0].inventory.equipment,
+            save.party[0].inventory.equipment
+        );
+        assert_eq!(
+            again.party[0].inventory.learned,
+            save.party[0].inventory.learned
+        );
+    }
+
+    #[test]
+    fn newer_version_fails_with_a_message() {
+        let text = FIXTURES[FIXTURES.len() - 1].1.replacen(
+            &format!("\"version\": {}", SAVE_VERSION),
+            &format!("\"version\": {}", SAVE_VERSION + 1),
+            1,
+        );
+        let err = SaveData::from_json(&text)
+            .err()
+            .expect("newer saves must not load");
+        assert!(format!("{:#}", err).contains("newer version"), "{:#}", err);
+    }
+
+    #[test]
+    fn missing_or_invalid_version_fails() {
+        assert!(SaveData::from_json("{}").is_err());
+        assert!(SaveData::from_json(r#"{"version": 0}"#).is_err());
+        assert!(SaveData::from_json(r#"{"version": "2"}"#).is_err());
+        assert!(SaveData::from_json("not json").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+use crate::character_status::CharacterStatus;
+use crate::events::{GameEvent, RunState};
+use crate::inventory::Inventory;
+use crate::loading::FontAssets;
+use crate::map::{Map, Position};
+use crate::party::{MemberData, PartyMember};
+use crate::save::{
+    format_play_time, format_timestamp, PendingLoad, PlayTime, SaveData, SaveStorage, SAVE_SLOTS,
+};
+use crate::setup::{ForState, MapCamera};
+use crate::AppState;
+use bevy::prelude::*;
+
+pub struct SaveSlotsPlugin;
+
+// This plugin draws the slot selection screen opened from the title screen and from towns.
+impl Plugin for SaveSlotsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SlotScreen>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::SaveSlots).with_system(setup_slots_ui.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::SaveSlots).with_system(update_slots.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu).with_system(open_from_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::InGameEvent).with_system(open_from_town.system()),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlotMode {
+    // opened from the title screen
+    Load,
+    // opened from a town
+    Save,
+}
+
+pub struct SlotScreen {
+    pub mode: SlotMode,
+    pub selected: usize,
+    // slot marked with 'C', pasted into the selected slot on the next 'C'
+    pub copy_from: Option<usize>,
+    // slot marked with 'X', deleted by the next 'X' on the same slot
+    pub delete: Option<usize>,
+    pub message: String,
+}
+impl Default for SlotScreen {
+    fn default() -> Self {
+        SlotScreen {
+            mode: SlotMode::Load,
+            selected: 0,
+            copy_from: None,
+            delete: None,
+            message: String::new(),
+        }
+    }
+}
+impl SlotScreen {
+    pub fn open(&mut self, mode: SlotMode) {
+        self.mode = mode;
+        self.copy_from = None;
+        self.delete = None;
+        self.message = String::new();
+    }
+}
+
+pub struct UiSlotsText;
+
+// Press 'C' on the title screen to load a slot
+fn open_from_menu(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut screen: ResMut<SlotScreen>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::C) {
+        keyboard_input.reset(KeyCode::C);
+        screen.open(SlotMode::Load);
+        state.set(AppState::SaveSlots).unwrap();
+    }
+}
+
+// Press 'S' while the town message is shown to save into a slot
+fn open_from_town(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    runstate: Res<RunState>,
+    mut screen: ResMut<SlotScreen>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if !matches!(runstate.event, Some(GameEvent::TownArrived(_, _))) {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::S) {
+        keyboard_input.reset(KeyCode::S);
+        screen.open(SlotMode::Save);
+        state.set(AppState::SaveSlots).unwrap();
+    }
+}
+
+fn setup_slots_ui(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    screen: Res<SlotScreen>,
+    storage: Res<SaveStorage>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                padding: Rect::all(Val::Px(20.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::BLACK.into()),
+            ..Default::default()
+        })
+        .insert(ForState {
+            states: vec![AppState::SaveSlots],
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.)),
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        slots_text(&screen, &storage),
+                        TextStyle {
+                            font: font_assets.pixel_mplus.clone(),
+                            font_size: 36.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ForState {
+                    states: vec![AppState::SaveSlots],
+                })
+                .insert(UiSlotsText);
+        });
+}
+
+fn slots_text(screen: &SlotScreen, storage: &SaveStorage) -> String {
+    let mut text = match screen.mode {
+        SlotMode::Load => "Load\n\n".to_string(),
+        SlotMode::Save => "Save\n\n".to_string(),
+    };
+    for slot in 0..SAVE_SLOTS {
+        let cursor = if slot == screen.selected { ">" } else { " " };
+        let mark = if screen.delete == Some(slot) {
+            " (delete?)"
+        } else if screen.copy_from == Some(slot) {
+            " (copy)"
+        } else {
+            ""
+        };
+        match storage.load(slot) {
+            Ok(Some(save)) => {
+                let (visited, towns) = save.towns_visited();
+                text.push_str(&format!(
+                    "{} Slot {}{}  Lv {:>2}  Party {}  Time {}  Towns {}/{}\n      Seed {}  {}\n",
+                    cursor,
+                    slot + 1,
+                    mark,
+                    save.party[0].status.lv,
+                    save.party.len(),
+                    format_play_time(save.play_time),
+                    visited,
+                    towns,
+                    save.map.seed,
+                    format_timestamp(save.saved_at)
+                ));
+            }
+            Ok(None) => {
+                text.push_str(&format!("{} Slot {}  - empty -\n\n", cursor, slot + 1));
+            }
+            Err(err) => {
+                text.push_str(&format!(
+                    "{} Slot {}{}  broken\n      {:#}\n",
+                    cursor,
+                    slot + 1,
+                    mark,
+                    err
+                ));
+            }
+        }
+    }
+    text.push_str(match screen.mode {
+        SlotMode::Load => "\nEnter: Load  C: Copy  X: Delete  Esc: Back\n",
+        SlotMode::Save => "\nEnter: Save  C: Copy  X: Delete  Esc: Back\n",
+    });
+    text.push_str(&screen.message);
+    text
+}
+
+fn update_slots(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut screen: ResMut<SlotScreen>,
+    storage: Res<SaveStorage>,
+    mut state: ResMut<State<AppState>>,
+    mut pending_load: ResMut<PendingLoad>,
+    mut play_time: ResMut<PlayTime>,
+    map: Res<Map>,
+    position_query: Query<&Position, With<MapCamera>>,
+    party_query: Query<(&CharacterStatus, &Inventory, &PartyMember)>,
+    mut text_query: Query<&mut Text, With<UiSlotsText>>,
+) {
+    let selected = screen.selected;
+    if keyboard_input.just_pressed(KeyCode::Up) || keyboard_input.just_pressed(KeyCode::W) {
+        screen.selected = (selected + SAVE_SLOTS - 1) % SAVE_SLOTS;
+    } else if keyboard_input.just_pressed(KeyCode::Down) || keyboard_input.just_pressed(KeyCode::S)
+    {
+        screen.selected = (selected + 1) % SAVE_SLOTS;
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        match screen.mode {
+            SlotMode::Load => state.set(AppState::Menu).unwrap(),
+            SlotMode::Save => state.set(AppState::InGameExplore).unwrap(),
+        }
+        return;
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        keyboard_input.reset(KeyCode::Return);
+        screen.delete = None;
+        match screen.mode {
+            SlotMode::Load => match storage.load(selected) {
+                Ok(Some(save)) => {
+                    play_time.seconds = save.play_time;
+                    pending_load.save = Some(save);
+                    state.set(AppState::InGameMap).unwrap();
+                    return;
+                }
+                Ok(None) => screen.message = format!("Slot {} is empty.", selected + 1),
+                Err(err) => {
+                    error!("{:#}", err);
+                    screen.message = format!("Can't load slot {}.", selected + 1);
+                }
+            },
+            SlotMode::Save => {
+                let position = position_query.single().unwrap();
+                let mut party: Vec<(&CharacterStatus, &Inventory, &PartyMember)> =
+                    party_query.iter().collect();
+                party.sort_unstable_by_key(|(_, _, member)| member.order);
+                let party = party
+                    .into_iter()
+                    .map(|(status, inventory, _)| MemberData {
+                        status: status.clone(),
+                        inventory: inventory.clone(),
+                    })
+                    .collect();
+                let save = SaveData::new(&map, position, party, &play_time);
+                screen.message = match storage.save(selected, &save) {
+                    Ok(()) => format!("Saved to slot {}.", selected + 1),
+                    Err(err) => {
+                        error!("{:#}", err);
+                        format!("Failed to save: {:#}", err)
+                    }
+                };
+            }
+        }
+    } else if keyboard_input.just_pressed(KeyCode::C) {
+        keyboard_input.reset(KeyCode::C);
+        screen.delete = None;
+        match screen.copy_from {
+            None => {
+                screen.copy_from = Some(selected);
+                screen.message = "Select a slot and press C again to copy.".to_string();
+            }
+            Some(from) if from == selected => {
+                screen.copy_from = None;
+                screen.message = String::new();
+            }
+            Some(from) => {
+                screen.copy_from = None;
+                screen.message = match storage.copy(from, selected) {
+                    Ok(()) => format!("Copied slot {} to slot {}.", from + 1, selected + 1),
+                    Err(err) => {
+                        error!("{:#}", err);
+                        format!("Failed to copy: {:#}", err)
+                    }
+                };
+            }
+        }
+    } else if keyboard_input.just_pressed(KeyCode::X)
+        || keyboard_input.just_pressed(KeyCode::Delete)
+    {
+        keyboard_input.reset(KeyCode::X);
+        keyboard_input.reset(KeyCode::Delete);
+        if screen.delete == Some(selected) {
+            screen.delete = None;
+            screen.message = match storage.delete(selected) {
+                Ok(()) => format!("Deleted slot {}.", selected + 1),
+                Err(err) => {
+                    error!("{:#}", err);
+                    format!("Failed to delete: {:#}", err)
+                }
+            };
+        } else {
+            screen.delete = Some(selected);
+            screen.message = format!("Press X again to delete slot {}.", selected + 1);
+        }
+    } else {
+        return;
+    }
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = slots_text(&screen, &storage);
+    }
+}
@@ -26,6 +26,9 @@ impl Plugin for SetupPlugin {
             .add_system_set(
                 SystemSet::on_enter(AppState::InGameEvent)
                     .with_system(state_enter_despawn.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::SaveSlots).with_system(state_enter_despawn.system()),
             );
     }
 }
@@ -0,0 +1,284 @@
+use crate::inventory::{Inventory, Item};
+use bevy::prelude::*;
+use std::array::IntoIter;
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+pub struct ShopPlugin;
+
+// This plugin holds the prices of the town shops and the town menu
+impl Plugin for ShopPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ShopData>().init_resource::<TownMenu>();
+    }
+}
+
+// Gold of the hero at the start of a new game
+pub const STARTING_GOLD: u32 = 50;
+// A night at the inn, per hero
+pub const INN_PRICE: u32 = 10;
+
+pub struct ShopData {
+    // what the shops sell, in the order of the shop list
+    pub goods: Vec<Item>,
+    pub prices: HashMap<Item, u32>,
+}
+impl Default for ShopData {
+    fn default() -> Self {
+        let prices = HashMap::<_, _>::from_iter(IntoIter::new([
+            (Item::Herb, 10),
+            (Item::Antidote, 20),
+            (Item::Wing, 25),
+            (Item::MagicWater, 30),
+            (Item::CopperSword, 40),
+            (Item::LeatherShield, 50),
+            (Item::LeatherArmor, 60),
+            (Item::IronHead, 80),
+            (Item::IronSword, 150),
+            (Item::IronArm, 120),
+            (Item::IronLeg, 120),
+            (Item::IronBody, 200),
+            (Item::FairyShield, 400),
+            (Item::WisdomRing, 400),
+            (Item::SpeedRing, 300),
+        ]));
+        ShopData {
+            goods: vec![
+                Item::Herb,
+                Item::MagicWater,
+                Item::Antidote,
+                Item::Wing,
+                Item::CopperSword,
+                Item::IronSword,
+                Item::LeatherShield,
+                Item::LeatherArmor,
+                Item::IronHead,
+                Item::IronArm,
+                Item::IronLeg,
+                Item::IronBody,
+                Item::FairyShield,
+                Item::WisdomRing,
+                Item::SpeedRing,
+            ],
+            prices,
+        }
+    }
+}
+
+impl ShopData {
+    pub fn price(&self, item: &Item) -> Option<u32> {
+        self.prices.get(item).copied()
+    }
+    // Shops buy back at half the price, items they don't sell are worth nothing to them
+    pub fn sell_price(&self, item: &Item) -> Option<u32> {
+        self.price(item).map(|price| price / 2)
+    }
+    // Items of the inventory the shop buys back
+    pub fn sellable(&self, inventory: &Inventory) -> Vec<Item> {
+        inventory
+            .items
+            .iter()
+            .chain(inventory.consumables.iter().map(|(item, _)| item))
+            .filter(|item| self.price(item).is_some())
+            .copied()
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TownScreen {
+    Menu,
+    Buy,
+    Sell,
+}
+
+// Entries of the town menu
+pub const TOWN_MENU: [&str; 3] = ["Inn", "Shop", "Leave"];
+
+// Where the player is in the town menu
+#[derive(Clone, Debug)]
+pub struct TownMenu {
+    pub screen: TownScreen,
+    pub cursor: usize,
+    // result of the last choice, e.g. "Not enough gold."
+    pub message: String,
+}
+impl Default for TownMenu {
+    fn default() -> Self {
+        TownMenu {
+            screen: TownScreen::Menu,
+            cursor: 0,
+            message: String::new(),
+        }
+    }
+}
+
+impl TownMenu {
+    pub fn open(&mut self, screen: TownScreen) {
+        self.screen = screen;
+        self.cursor = 0;
+    }
+    // Number of entries of the current screen, the shop lists end with "Back"
+    pub fn len(&self, shop_data: &ShopData, inventory: &Inventory) -> usize {
+        match self.screen {
+            TownScreen::Menu => TOWN_MENU.len(),
+            TownScreen::Buy => shop_data.goods.len() + 2,
+            TownScreen::Sell => shop_data.sellable(inventory).len() + 1,
+        }
+    }
+    pub fn up(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+        }
+    }
+    pub fn down(&mut self, len: usize) {
+        self.cursor = (self.cursor + 1).min(len.max(1) - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::character_status::Skill;
+
+    fn rich() -> Inventory {
+        Inventory {
+            gold: 500,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn buying_spends_gold() {
+        let mut inventory = rich();
+        assert!(inventory.buy(Item::IronHead, 80).is_ok());
+        assert_eq!(inventory.gold, 420);
+        assert_eq!(inventory.items, vec![Item::IronHead]);
+    }
+
+    #[test]
+    fn cannot_buy_without_gold_or_twice() {
+        let mut inventory = Inventory::default();
+        assert!(inventory.buy(Item::IronHead, 80).is_err());
+        let mut inventory = rich();
+        inventory.buy(Item::IronHead, 80).unwrap();
+        assert!(inventory.buy(Item::IronHead, 80).is_err());
+        assert_eq!(inventory.gold, 420);
+    }
+
+    #[test]
+    fn selling_takes_the_skill_away() {
+        let data = ShopData::default();
+        let mut inventory = rich();
+        inventory.add_item(Item::SpellHeal(1));
+        inventory.sell(Item::SpellHeal(1), 0);
+        assert!(inventory.items.is_empty());
+        assert_eq!(inventory.skills, vec![Skill::Sword]);
+        inventory.buy(Item::IronArm, 120).unwrap();
+        assert_eq!(data.sellable(&inventory), vec![Item::IronArm]);
+        inventory.sell(Item::IronArm, data.sell_price(&Item::IronArm).unwrap());
+        assert_eq!(inventory.gold, 440);
+        assert!(inventory.items.is_empty());
+    }
+
+    #[test]
+    fn selling_keeps_learned_spells() {
+        let heal = Skill::Spell(Item::SpellHeal(1));
+        // 本を買ってからレベルで覚えても、覚えてから本を買っても同じ
+        for &book_first in [true, false].iter() {
+            let mut inventory = rich();
+            if book_first {
+                inventory.add_item(Item::SpellHeal(1));
+                inventory.learn(Item::SpellHeal(1));
+            } else {
+                inventory.learn(Item::SpellHeal(1));
+                inventory.add_item(Item::SpellHeal(1));
+            }
+            inventory.sell(Item::SpellHeal(1), 0);
+            assert!(inventory.items.is_empty());
+            assert_eq!(inventory.skills, vec![Skill::Sword, heal]);
+        }
+    }
+
+    #[test]
+    fn consumables_stack() {
+        let mut inventory = rich();
+        inventory.buy(Item::Herb, 10).unwrap();
+        inventory.buy(Item::Herb, 10).unwrap();
+        assert_eq!(inventory.count(&Item::Herb), 2);
+        assert!(inventory.items.is_empty());
+        assert_eq!(
+            inventory.commands(&inventory.consumables),
+            vec![
+                Skill::Sword,
+                Skill::Item(Item::Herb),
+                Skill::Defend,
+                Skill::Run
+            ]
+        );
+        inventory.sell(Item::Herb, 5);
+        assert_eq!(inventory.count(&Item::Herb), 1);
+        assert_eq!(inventory.gold, 485);
+    }
+
+    #[test]
+    fn the_last_one_leaves_the_battle_menu() {
+        let mut inventory = rich();
+        inventory.add_item(Item::Antidote);
+        assert!(inventory.consume(&Item::Antidote));
+        assert!(!inventory.consume(&Item::Antidote));
+        assert_eq!(
+            inventory.commands(&inventory.consumables),
+            vec![Skill::Sword, Skill::Defend, Skill::Run]
+        );
+    }
+
+    #[test]
+    fn the_party_shares_the_items_of_the_hero() {
+        let mut hero = rich();
+        hero.buy(Item::Herb, 10).unwrap();
+        let knight = Inventory::default();
+        assert_eq!(
+            knight.commands(&hero.consumables),
+            vec![
+                Skill::Sword,
+                Skill::Item(Item::Herb),
+                Skill::Defend,
+                Skill::Run
+            ]
+        );
+        assert!(knight
+            .skill_list(&hero.consumables, |_| true)
+            .contains("Herb x1"));
+    }
+
+    #[test]
+    fn wings_are_not_used_in_battle() {
+        let mut inventory = rich();
+        inventory.add_item(Item::Wing);
+        assert_eq!(inventory.count(&Item::Wing), 1);
+        assert!(!inventory
+            .commands(&inventory.consumables)
+            .contains(&Skill::Item(Item::Wing)));
+    }
+
+    #[test]
+    fn treasures_the_shop_does_not_sell_are_kept() {
+        let data = ShopData::default();
+        let mut inventory = rich();
+        inventory.add_item(Item::SpellFire(1));
+        inventory.add_item(Item::HeroSword);
+        assert!(data.sellable(&inventory).is_empty());
+    }
+
+    #[test]
+    fn cursor_stays_in_the_menu() {
+        let mut menu = TownMenu::default();
+        menu.up();
+        assert_eq!(menu.cursor, 0);
+        for _ in 0..5 {
+            menu.down(TOWN_MENU.len());
+        }
+        assert_eq!(menu.cursor, TOWN_MENU.len() - 1);
+    }
+}
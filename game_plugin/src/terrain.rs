@@ -0,0 +1,234 @@
+use crate::map::{Field, MAP_SIZE};
+use core::fmt;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+// Fills every position of the world map with Grass, Forest, Mountain or Water.
+// Towns, the castle and the start position are placed afterwards by Map::generate_map.
+pub trait TerrainGenerator {
+    fn generate(&self, rng: &mut ChaCha8Rng, fields: &mut HashMap<(i32, i32), Field>);
+}
+
+// Generator selectable on the title screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TerrainKind {
+    Scatter,
+    Noise,
+}
+impl Default for TerrainKind {
+    fn default() -> Self {
+        TerrainKind::Scatter
+    }
+}
+impl Display for TerrainKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, fmt)
+    }
+}
+impl TerrainKind {
+    pub fn generator(&self) -> Box<dyn TerrainGenerator> {
+        match self {
+            TerrainKind::Scatter => Box::new(ScatterTerrain),
+            TerrainKind::Noise => Box::new(NoiseTerrain::default()),
+        }
+    }
+    pub fn next(&self) -> Self {
+        match self {
+            TerrainKind::Scatter => TerrainKind::Noise,
+            TerrainKind::Noise => TerrainKind::Scatter,
+        }
+    }
+}
+
+// Independent coin flips per tile with small mountain clusters
+pub struct ScatterTerrain;
+
+impl TerrainGenerator for ScatterTerrain {
+    fn generate(&self, rng: &mut ChaCha8Rng, fields: &mut HashMap<(i32, i32), Field>) {
+        let width = MAP_SIZE[0] as i32;
+        let height = MAP_SIZE[1] as i32;
+        for y in 0..height {
+            for x in 0..width {
+                let pos = (x - width / 2, y - height / 2); // -chunk_height/2 < y < chunk_height/2
+                if rng.gen_bool(1. / 60.) {
+                    let max = rng.gen_range(10..60);
+                    for _i in 0..max {
+                        let pos = (
+                            (pos.0 + rng.gen_range(-3..4)).clamp(-&width / 2, &width / 2 - 1),
+                            (pos.1 + rng.gen_range(-3..4)).clamp(-&height / 2, &height / 2 - 1),
+                        );
+                        fields.insert(pos, Field::Mountain);
+                    }
+                }
+                if 0 < y && y < height - 1 && 0 < x && x < width - 1 {
+                    if rng.gen_bool(1. / 12.) {
+                        fields.insert(pos, Field::Water);
+                    }
+                }
+                if rng.gen_bool(1. / 6.) {
+                    fields.insert(pos, Field::Forest);
+                }
+            }
+        }
+    }
+}
+
+// Layered value noise for elevation and moisture.
+// Lattice cells divide MAP_SIZE evenly and wrap, so the torus seams line up.
+pub struct NoiseTerrain {
+    // (cell size in tiles, weight)
+    pub octaves: Vec<(i32, f32)>,
+    pub sea_level: f32,
+    pub mountain_level: f32,
+    pub forest_moisture: f32,
+}
+impl Default for NoiseTerrain {
+    fn default() -> Self {
+        NoiseTerrain {
+            octaves: vec![(16, 0.55), (8, 0.3), (4, 0.15)],
+            sea_level: 0.38,
+            mountain_level: 0.66,
+            forest_moisture: 0.55,
+        }
+    }
+}
+
+impl TerrainGenerator for NoiseTerrain {
+    fn generate(&self, rng: &mut ChaCha8Rng, fields: &mut HashMap<(i32, i32), Field>) {
+        let width = MAP_SIZE[0] as i32;
+        let height = MAP_SIZE[1] as i32;
+        let elevation_salt: u64 = rng.gen();
+        let moisture_salt: u64 = rng.gen();
+        for y in 0..height {
+            for x in 0..width {
+                let pos = (x - width / 2, y - height / 2);
+                let elevation = self.fractal(elevation_salt, x, y);
+                let moisture = self.fractal(moisture_salt, x, y);
+                let field = if elevation < self.sea_level {
+                    Field::Water
+                } else if elevation > self.mountain_level {
+                    Field::Mountain
+                } else if moisture > self.forest_moisture {
+                    Field::Forest
+                } else {
+                    Field::Grass
+                };
+                fields.insert(pos, field);
+            }
+        }
+    }
+}
+
+impl NoiseTerrain {
+    // 0.0 ..= 1.0
+    fn fractal(&self, salt: u64, x: i32, y: i32) -> f32 {
+        let total: f32 = self.octaves.iter().map(|(_cell, weight)| weight).sum();
+        self.octaves
+            .iter()
+            .enumerate()
+            .map(|(i, (cell, weight))| value_noise(salt ^ i as u64, *cell, x, y) * weight)
+            .sum::<f32>()
+            / total
+    }
+}
+
+// Bilinear interpolation of random lattice values, periodic over MAP_SIZE
+fn value_noise(salt: u64, cell: i32, x: i32, y: i32) -> f32 {
+    let cells_x = (MAP_SIZE[0] as i32 / cell).max(1);
+    let cells_y = (MAP_SIZE[1] as i32 / cell).max(1);
+    let (ix, iy) = (x.div_euclid(cell), y.div_euclid(cell));
+    let fx = smoothstep(x.rem_euclid(cell) as f32 / cell as f32);
+    let fy = smoothstep(y.rem_euclid(cell) as f32 / cell as f32);
+    let lattice =
+        |lx: i32, ly: i32| lattice_value(salt, lx.rem_euclid(cells_x), ly.rem_euclid(cells_y));
+    let top = lerp(lattice(ix, iy), lattice(ix + 1, iy), fx);
+    let bottom = lerp(lattice(ix, iy + 1), lattice(ix + 1, iy + 1), fx);
+    lerp(top, bottom, fy)
+}
+
+// Integer hash so the same seed gives the same value on every platform
+fn lattice_value(salt: u64, x: i32, y: i32) -> f32 {
+    let mut h = salt ^ ((x as u32 as u64) << 32 | y as u32 as u64);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    // Water, lowland, mountain. Neighbours never skip the lowland in between.
+    fn step(field: &Field) -> i32 {
+        match field {
+            Field::Water => 0,
+            Field::Mountain => 2,
+            _ => 1,
+        }
+    }
+
+    #[test]
+    fn noise_is_periodic() {
+        let noise = NoiseTerrain::default();
+        let width = MAP_SIZE[0] as i32;
+        let height = MAP_SIZE[1] as i32;
+        for salt in 0..50 {
+            for y in 0..height {
+                assert_eq!(noise.fractal(salt, width, y), noise.fractal(salt, 0, y));
+            }
+            for x in 0..width {
+                assert_eq!(noise.fractal(salt, x, height), noise.fractal(salt, x, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn noise_seams_line_up() {
+        let width = MAP_SIZE[0] as i32;
+        let height = MAP_SIZE[1] as i32;
+        for seed in 0..200 {
+            let mut fields = HashMap::new();
+            NoiseTerrain::default().generate(&mut ChaCha8Rng::seed_from_u64(seed), &mut fields);
+            let differ =
+                |a: (i32, i32), b: (i32, i32)| (step(&fields[&a]) - step(&fields[&b])).abs();
+            // 内側の隣同士と同じく一段しか変わらない
+            for y in -height / 2..height / 2 {
+                for x in -width / 2..width / 2 - 1 {
+                    assert!(
+                        differ((x, y), (x + 1, y)) <= 1,
+                        "seed {} at {:?}",
+                        seed,
+                        (x, y)
+                    );
+                }
+                let seam = ((-width / 2, y), (width / 2 - 1, y));
+                assert!(differ(seam.0, seam.1) <= 1, "seed {} at {:?}", seed, seam);
+            }
+            for x in -width / 2..width / 2 {
+                for y in -height / 2..height / 2 - 1 {
+                    assert!(
+                        differ((x, y), (x, y + 1)) <= 1,
+                        "seed {} at {:?}",
+                        seed,
+                        (x, y)
+                    );
+                }
+                let seam = ((x, -height / 2), (x, height / 2 - 1));
+                assert!(differ(seam.0, seam.1) <= 1, "seed {} at {:?}", seed, seam);
+            }
+        }
+    }
+}
@@ -0,0 +1,721 @@
+use crate::inventory::Item;
+use crate::map::{unreachable_fields, EncounterZone, Field, MAP_SIZE, MAP_TEXTURE_SIZE};
+use crate::world_map::{to_position, WorldMapAsset};
+use anyhow::{anyhow, bail, Context};
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// Imports maps made with the Tiled editor (https://www.mapeditor.org), as XML (.tmx) or JSON (.tmj).
+//
+// The map must be MAP_SIZE tiles large and use `textures/tiles/land.png` as its first tileset.
+// Tile layers (CSV encoded in .tmx):
+//   "background"  drawn on the Dense layer, decides the field of every tile
+//                 with the Grass, Forest, Mountain and Water sprites
+//   "foreground"  optional, drawn on the Sparse layer over the background, any sprite
+//                 of the atlas and it does not change the field
+// Object layer objects, by type (or class):
+//   "town"        with a string property `item`, e.g. `SpellFire(1)`
+//   "castle"      the Lich castle
+//   "spawn"       start position of the player, the map center when missing
+//   "encounter"   rectangle with optional properties `rate` (int, 0 for none) and `table` (field name)
+// Every town and the castle must be reachable on foot from the spawn point.
+#[derive(Default)]
+pub struct TiledMapLoader;
+
+impl AssetLoader for TiledMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let text = std::str::from_utf8(bytes)?;
+            let tiled_map = match load_context.path().extension().and_then(|e| e.to_str()) {
+                Some("tmj") | Some("json") => parse_tmj(text)?,
+                _ => parse_tmx(text)?,
+            };
+            let world_map = tiled_map.into_world_map()?;
+            load_context.set_default_asset(LoadedAsset::new(world_map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx", "tmj"]
+    }
+}
+
+// The parts of a Tiled map both formats have in common
+struct TiledMap {
+    width: usize,
+    height: usize,
+    tile_width: f32,
+    tile_height: f32,
+    first_gid: u32,
+    tile_layers: Vec<(String, Vec<u32>)>,
+    objects: Vec<TiledObject>,
+}
+
+struct TiledObject {
+    name: String,
+    kind: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    properties: HashMap<String, String>,
+}
+
+// Tiled stores flip flags in the highest bits of a gid
+const GID_MASK: u32 = 0x1fff_ffff;
+
+fn parse_tmx(text: &str) -> anyhow::Result<TiledMap> {
+    let document = roxmltree::Document::parse(text).context("invalid TMX file")?;
+    let root = document.root_element();
+    if root.tag_name().name() != "map" {
+        bail!("TMX root element must be <map>");
+    }
+    let first_gid = root
+        .children()
+        .find(|node| node.has_tag_name("tileset"))
+        .ok_or_else(|| anyhow!("the map has no tileset"))?
+        .attribute("firstgid")
+        .unwrap_or("1");
+
+    let mut tile_layers = Vec::new();
+    let mut objects = Vec::new();
+    for node in root.descendants() {
+        if node.has_tag_name("layer") {
+            let name = node.attribute("name").unwrap_or_default().to_string();
+            let data = node
+                .children()
+                .find(|child| child.has_tag_name("data"))
+                .ok_or_else(|| anyhow!("layer '{}' has no data", name))?;
+            let gids = match data.attribute("encoding") {
+                Some("csv") => data
+                    .text()
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|gid| gid.trim())
+                    .filter(|gid| !gid.is_empty())
+                    .map(|gid| {
+                        gid.parse::<u32>()
+                            .with_context(|| format!("invalid gid '{}' in layer '{}'", gid, name))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+                None => data
+                    .children()
+                    .filter(|child| child.has_tag_name("tile"))
+                    .map(|tile| tmx_number(tile.attribute("gid").unwrap_or("0"), "gid"))
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+                Some(encoding) => bail!(
+                    "layer '{}' uses '{}' encoding, save it as CSV in Tiled",
+                    name,
+                    encoding
+                ),
+            };
+            tile_layers.push((name, gids));
+        } else if node.has_tag_name("object") && is_map_object(node) {
+            let mut properties = HashMap::new();
+            for property in node.descendants().filter(|p| p.has_tag_name("property")) {
+                if let Some(name) = property.attribute("name") {
+                    let value = property
+                        .attribute("value")
+                        .or_else(|| property.text())
+                        .unwrap_or_default();
+                    properties.insert(name.to_string(), value.to_string());
+                }
+            }
+            let mut object = TiledObject {
+                name: node.attribute("name").unwrap_or_default().to_string(),
+                kind: node
+                    .attribute("type")
+                    .or_else(|| node.attribute("class"))
+                    .unwrap_or_default()
+                    .to_string(),
+                x: tmx_number(node.attribute("x").unwrap_or("0"), "x")?,
+                y: tmx_number(node.attribute("y").unwrap_or("0"), "y")?,
+                width: tmx_number(node.attribute("width").unwrap_or("0"), "width")?,
+                height: tmx_number(node.attribute("height").unwrap_or("0"), "height")?,
+                properties,
+            };
+            // tile objects are anchored at their bottom left corner
+            if node.attribute("gid").is_some() {
+                object.y -= object.height;
+            }
+            objects.push(object);
+        }
+    }
+
+    Ok(TiledMap {
+        width: tmx_number(root.attribute("width").unwrap_or("0"), "width")?,
+        height: tmx_number(root.attribute("height").unwrap_or("0"), "height")?,
+        tile_width: tmx_number(root.attribute("tilewidth").unwrap_or("0"), "tilewidth")?,
+        tile_height: tmx_number(root.attribute("tileheight").unwrap_or("0"), "tileheight")?,
+        first_gid: tmx_number(first_gid, "firstgid")?,
+        tile_layers,
+        objects,
+    })
+}
+
+// Objects inside a tileset describe tile collision shapes, not map objects
+fn is_map_object(node: roxmltree::Node) -> bool {
+    node.ancestors()
+        .skip(1)
+        .all(|ancestor| !ancestor.has_tag_name("tileset"))
+}
+
+fn tmx_number<T: std::str::FromStr>(value: &str, name: &str) -> anyhow::Result<T> {
+    value
+        .parse::<T>()
+        .map_err(|_| anyhow!("invalid {} '{}'", name, value))
+}
+
+fn parse_tmj(text: &str) -> anyhow::Result<TiledMap> {
+    let root: Value = serde_json::from_str(text).context("invalid TMJ file")?;
+    let first_gid = root["tilesets"]
+        .get(0)
+        .ok_or_else(|| anyhow!("the map has no tileset"))?["firstgid"]
+        .as_u64()
+        .unwrap_or(1) as u32;
+
+    let mut tile_layers = Vec::new();
+    let mut objects = Vec::new();
+    let mut layers: Vec<&Value> = root["layers"]
+        .as_array()
+        .ok_or_else(|| anyhow!("the map has no layers"))?
+        .iter()
+        .collect();
+    while let Some(layer) = layers.pop() {
+        let name = layer["name"].as_str().unwrap_or_default().to_string();
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                let data = layer["data"]
+                    .as_array()
+                    .ok_or_else(|| anyhow!("layer '{}' must use CSV (array) tile data", name))?;
+                let gids = data
+                    .iter()
+                    .map(|gid| {
+                        gid.as_u64()
+                            .map(|gid| gid as u32)
+                            .ok_or_else(|| anyhow!("invalid gid {} in layer '{}'", gid, name))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                tile_layers.insert(0, (name, gids));
+            }
+            Some("objectgroup") => {
+                let mut group = Vec::new();
+                for object in layer["objects"].as_array().into_iter().flatten() {
+                    let mut properties = HashMap::new();
+                    for property in object["properties"].as_array().into_iter().flatten() {
+                        if let Some(name) = property["name"].as_str() {
+                            let value = match &property["value"] {
+                                Value::String(value) => value.clone(),
+                                value => value.to_string(),
+                            };
+                            properties.insert(name.to_string(), value);
+                        }
+                    }
+                    let mut tiled_object = TiledObject {
+                        name: object["name"].as_str().unwrap_or_default().to_string(),
+                        kind: object["type"]
+                            .as_str()
+                            .filter(|kind| !kind.is_empty())
+                            .or_else(|| object["class"].as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                        x: object["x"].as_f64().unwrap_or(0.) as f32,
+                        y: object["y"].as_f64().unwrap_or(0.) as f32,
+                        width: object["width"].as_f64().unwrap_or(0.) as f32,
+                        height: object["height"].as_f64().unwrap_or(0.) as f32,
+                        properties,
+                    };
+                    if object.get("gid").is_some() {
+                        tiled_object.y -= tiled_object.height;
+                    }
+                    group.push(tiled_object);
+                }
+                // layers are taken from the back, objects keep the order of the file
+                objects.splice(0..0, group);
+            }
+            Some("group") => {
+                layers.extend(layer["layers"].as_array().into_iter().flatten());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(TiledMap {
+        width: root["width"].as_u64().unwrap_or(0) as usize,
+        height: root["height"].as_u64().unwrap_or(0) as usize,
+        tile_width: root["tilewidth"].as_f64().unwrap_or(0.) as f32,
+        tile_height: root["tileheight"].as_f64().unwrap_or(0.) as f32,
+        first_gid,
+        tile_layers,
+        objects,
+    })
+}
+
+impl TiledMap {
+    fn into_world_map(self) -> anyhow::Result<WorldMapAsset> {
+        let width = MAP_SIZE[0] as usize;
+        let height = MAP_SIZE[1] as usize;
+        if self.width != width || self.height != height {
+            bail!(
+                "the map is {}x{} tiles, expected {}x{}",
+                self.width,
+                self.height,
+                width,
+                height
+            );
+        }
+        if self.tile_width != MAP_TEXTURE_SIZE[0] as f32
+            || self.tile_height != MAP_TEXTURE_SIZE[1] as f32
+        {
+            bail!(
+                "tiles are {}x{} pixels, expected {}x{}",
+                self.tile_width,
+                self.tile_height,
+                MAP_TEXTURE_SIZE[0],
+                MAP_TEXTURE_SIZE[1]
+            );
+        }
+
+        for (name, gids) in self.tile_layers.iter() {
+            if gids.len() != width * height {
+                bail!(
+                    "layer '{}' has {} tiles, expected {}",
+                    name,
+                    gids.len(),
+                    width * height
+                );
+            }
+        }
+
+        let mut world_map = WorldMapAsset::default();
+        let background = self
+            .tile_layer("background")
+            .or_else(|| self.tile_layers.first().map(|(_name, gids)| gids))
+            .ok_or_else(|| anyhow!("the map has no tile layer"))?;
+        for (index, &gid) in background.iter().enumerate() {
+            let point = (index % width, index / width);
+            let sprite_index = self
+                .sprite_index(gid)
+                .ok_or_else(|| anyhow!("background tile {:?} is empty", point))?;
+            world_map
+                .fields
+                .insert(to_position(point), sprite_to_field(sprite_index, point)?);
+            world_map
+                .background
+                .insert(to_position(point), sprite_index);
+        }
+        if let Some(foreground) = self.tile_layer("foreground") {
+            for (index, &gid) in foreground.iter().enumerate() {
+                let point = (index % width, index / width);
+                if let Some(sprite_index) = self.sprite_index(gid) {
+                    world_map
+                        .foreground
+                        .insert(to_position(point), sprite_index);
+                }
+            }
+        }
+
+        let mut spawn = None;
+        let mut castle = false;
+        // (name, tile) of the towns and the castle
+        let mut places = Vec::new();
+        for object in self.objects.iter() {
+            let point = self.object_cell(object)?;
+            let position = to_position(point);
+            match object.kind.as_str() {
+                "town" => {
+                    let item = object
+                        .properties
+                        .get("item")
+                        .ok_or_else(|| {
+                            anyhow!("town '{}' at {:?} has no item", object.name, point)
+                        })?
+                        .parse::<Item>()
+                        .map_err(|e| anyhow!("town '{}' at {:?}: {}", object.name, point, e))?;
+                    let field = Field::Town {
+                        item,
+                        visited: false,
+                    };
+                    world_map.foreground.insert(position, field.sprite_index());
+                    world_map.fields.insert(position, field);
+                    places.push((object.name.as_str(), point));
+                }
+                "castle" => {
+                    if castle {
+                        bail!("the map has more than one castle");
+                    }
+                    castle = true;
+                    world_map
+                        .foreground
+                        .insert(position, Field::Castle.sprite_index());
+                    world_map.fields.insert(position, Field::Castle);
+                    places.push((object.name.as_str(), point));
+                }
+                "spawn" => {
+                    if spawn.is_some() {
+                        bail!("the map has more than one spawn point");
+                    }
+                    spawn = Some(position);
+                }
+                "encounter" => {
+                    let zone = EncounterZone {
+                        rate: match object.properties.get("rate") {
+                            Some(rate) => Some(rate.parse::<i32>().map_err(|_| {
+                                anyhow!("encounter '{}' has an invalid rate '{}'", object.name, rate)
+                            })?),
+                            None => None,
+                        },
+                        table: match object.properties.get("table") {
+                            Some(table) => Some(table_to_field(table).ok_or_else(|| {
+                                anyhow!(
+                                    "encounter '{}' has an unknown table '{}', expected Grass, Forest, Mountain or Floor",
+                                    object.name,
+                                    table
+                                )
+                            })?),
+                            None => None,
+                        },
+                    };
+                    for cell in self.object_cells(object) {
+                        world_map
+                            .encounter_zones
+                            .insert(to_position(cell), zone.clone());
+                    }
+                }
+                "" => warn!("ignoring object '{}' without a type", object.name),
+                kind => bail!(
+                    "object '{}' has an unknown type '{}', expected town, castle, spawn or encounter",
+                    object.name,
+                    kind
+                ),
+            }
+        }
+
+        if !castle {
+            bail!("the map has no castle object");
+        }
+        world_map.start = spawn.unwrap_or((0, 0));
+        match world_map.fields.get(&world_map.start) {
+            Some(field) if !field.is_obstacle() => {}
+            _ => bail!("the spawn point is not walkable"),
+        }
+        let unreachable = unreachable_fields(&world_map.fields, world_map.start);
+        let walled_in: Vec<String> = places
+            .iter()
+            .filter(|(_name, point)| unreachable.contains(&to_position(*point)))
+            .map(|(name, point)| format!("'{}' at {:?}", name, point))
+            .collect();
+        if !walled_in.is_empty() {
+            bail!(
+                "{} can't be reached from the spawn point",
+                walled_in.join(", ")
+            );
+        }
+
+        Ok(world_map)
+    }
+
+    fn tile_layer(&self, name: &str) -> Option<&Vec<u32>> {
+        self.tile_layers
+            .iter()
+            .find(|(layer, _gids)| layer.eq_ignore_ascii_case(name))
+            .map(|(_name, gids)| gids)
+    }
+
+    // None for empty tiles
+    fn sprite_index(&self, gid: u32) -> Option<usize> {
+        let gid = gid & GID_MASK;
+        if gid < self.first_gid {
+            None
+        } else {
+            Some((gid - self.first_gid) as usize)
+        }
+    }
+
+    // Cell under the center of the object
+    fn object_cell(&self, object: &TiledObject) -> anyhow::Result<(usize, usize)> {
+        let column = ((object.x + object.width / 2.) / self.tile_width).floor();
+        let row = ((object.y + object.height / 2.) / self.tile_height).floor();
+        if column < 0. || row < 0. || column >= self.width as f32 || row >= self.height as f32 {
+            bail!("object '{}' is outside the map", object.name);
+        }
+        Ok((column as usize, row as usize))
+    }
+
+    // Every cell the object rectangle touches
+    fn object_cells(&self, object: &TiledObject) -> Vec<(usize, usize)> {
+        let first_column = (object.x / self.tile_width).floor().max(0.) as usize;
+        let first_row = (object.y / self.tile_height).floor().max(0.) as usize;
+        let last_column = ((object.x + object.width) / self.tile_width)
+            .ceil()
+            .min(self.width as f32) as usize;
+        let last_row = ((object.y + object.height) / self.tile_height)
+            .ceil()
+            .min(self.height as f32) as usize;
+        let mut cells = Vec::new();
+        for row in first_row..last_row.max(first_row + 1).min(self.height) {
+            for column in first_column..last_column.max(first_column + 1).min(self.width) {
+                cells.push((column, row));
+            }
+        }
+        cells
+    }
+}
+
+// Sprites of textures/tiles/land.png
+fn sprite_to_field(sprite_index: usize, point: (usize, usize)) -> anyhow::Result<Field> {
+    match sprite_index {
+        0 => Ok(Field::Grass),
+        1 => Ok(Field::Forest),
+        2 => Ok(Field::Mountain),
+        3 => Ok(Field::Water),
+        _ => bail!(
+            "background tile {:?} uses sprite {}, place towns and the castle as objects",
+            point,
+            sprite_index
+        ),
+    }
+}
+
+fn table_to_field(table: &str) -> Option<Field> {
+    match table {
+        "Grass" => Some(Field::Grass),
+        "Forest" => Some(Field::Forest),
+        "Mountain" => Some(Field::Mountain),
+        "Floor" => Some(Field::Floor),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CELLS: usize = (MAP_SIZE[0] * MAP_SIZE[1]) as usize;
+
+    // All grass (gid 1) with the given gids at cells
+    fn layer(cells: &[((usize, usize), u32)]) -> Vec<u32> {
+        let mut gids = vec![1; CELLS];
+        for ((column, row), gid) in cells.iter() {
+            gids[row * MAP_SIZE[0] as usize + column] = *gid;
+        }
+        gids
+    }
+
+    fn tmx(width: u32, layers: &[(&str, Vec<u32>)], objects: &str) -> String {
+        let layers: String = layers
+            .iter()
+            .map(|(name, gids)| {
+                let csv: Vec<String> = gids.iter().map(|gid| gid.to_string()).collect();
+                format!(
+                    r#"<layer name="{}"><data encoding="csv">{}</data></layer>"#,
+                    name,
+                    csv.join(",")
+                )
+            })
+            .collect();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map width="{}" height="48" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" name="land" tilewidth="16" tileheight="16"/>
+ {}
+ <objectgroup name="objects">{}</objectgroup>
+</map>"#,
+            width, layers, objects
+        )
+    }
+
+    const TMX_OBJECTS: &str = r#"
+  <object name="town" type="town" x="80" y="80" width="16" height="16">
+   <properties><property name="item" value="SpellIce(2)"/></properties>
+  </object>
+  <object name="castle" type="castle" x="160" y="160" width="16" height="16"/>
+  <object name="spawn" type="spawn" x="520" y="376"/>
+  <object name="meadow" class="encounter" x="32" y="0" width="32" height="16">
+   <properties><property name="rate" type="int" value="0"/></properties>
+  </object>"#;
+
+    fn tmj(layers: &[(&str, Vec<u32>)], objects: Value) -> String {
+        let mut json_layers: Vec<Value> = layers
+            .iter()
+            .map(|(name, gids)| json!({"type": "tilelayer", "name": name, "data": gids}))
+            .collect();
+        json_layers.push(json!({"type": "objectgroup", "name": "objects", "objects": objects}));
+        json!({
+            "width": 64,
+            "height": 48,
+            "tilewidth": 16,
+            "tileheight": 16,
+            "tilesets": [{"firstgid": 1, "source": "land.tsx"}],
+            "layers": json_layers,
+        })
+        .to_string()
+    }
+
+    fn tmj_objects() -> Value {
+        json!([
+            {"name": "town", "type": "town", "x": 80, "y": 80, "width": 16, "height": 16,
+             "properties": [{"name": "item", "type": "string", "value": "SpellIce(2)"}]},
+            {"name": "castle", "type": "castle", "x": 160, "y": 160, "width": 16, "height": 16},
+            {"name": "spawn", "type": "spawn", "x": 520, "y": 376},
+            {"name": "meadow", "class": "encounter", "x": 32, "y": 0, "width": 32, "height": 16,
+             "properties": [{"name": "rate", "type": "int", "value": 0}]}
+        ])
+    }
+
+    fn check(world_map: &WorldMapAsset) {
+        // 地形は背景で決まり、前景の壁 (sprite 9) はただの飾り
+        assert_eq!(world_map.fields[&to_position((1, 1))], Field::Forest);
+        assert_eq!(world_map.fields[&to_position((2, 2))], Field::Grass);
+        assert_eq!(world_map.foreground[&to_position((2, 2))], 9);
+        assert_eq!(world_map.background[&to_position((1, 1))], 1);
+        assert_eq!(
+            world_map.fields[&to_position((5, 5))],
+            Field::Town {
+                item: Item::SpellIce(2),
+                visited: false
+            }
+        );
+        assert_eq!(world_map.fields[&to_position((10, 10))], Field::Castle);
+        assert_eq!(world_map.start, to_position((32, 23)));
+        assert_eq!(world_map.encounter_zones.len(), 2);
+        assert_eq!(
+            world_map.encounter_zones[&to_position((3, 0))].rate,
+            Some(0)
+        );
+    }
+
+    fn layers() -> Vec<(&'static str, Vec<u32>)> {
+        vec![
+            ("background", layer(&[((1, 1), 2)])),
+            ("foreground", {
+                let mut gids = vec![0; CELLS];
+                gids[2 * 64 + 2] = 10;
+                gids
+            }),
+        ]
+    }
+
+    #[test]
+    fn a_tmx_map_is_imported() {
+        let world_map = parse_tmx(&tmx(64, &layers(), TMX_OBJECTS))
+            .unwrap()
+            .into_world_map()
+            .unwrap();
+        check(&world_map);
+    }
+
+    #[test]
+    fn a_tmj_map_is_imported() {
+        let world_map = parse_tmj(&tmj(&layers(), tmj_objects()))
+            .unwrap()
+            .into_world_map()
+            .unwrap();
+        check(&world_map);
+    }
+
+    fn tmx_error(text: &str) -> String {
+        match parse_tmx(text).and_then(|map| map.into_world_map()) {
+            Ok(_) => panic!("the map should not load"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn tmj_error(text: &str) -> String {
+        match parse_tmj(text).and_then(|map| map.into_world_map()) {
+            Ok(_) => panic!("the map should not load"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn the_map_must_be_map_size() {
+        assert_eq!(
+            tmx_error(&tmx(10, &layers(), TMX_OBJECTS)),
+            "the map is 10x48 tiles, expected 64x48"
+        );
+        let short = vec![("background", vec![1; 100])];
+        assert_eq!(
+            tmj_error(&tmj(&short, tmj_objects())),
+            "layer 'background' has 100 tiles, expected 3072"
+        );
+    }
+
+    #[test]
+    fn the_map_needs_a_tile_layer() {
+        assert_eq!(
+            tmx_error(&tmx(64, &[], TMX_OBJECTS)),
+            "the map has no tile layer"
+        );
+        assert_eq!(
+            tmj_error(&tmj(&[], tmj_objects())),
+            "the map has no tile layer"
+        );
+        assert_eq!(
+            tmj_error(r#"{"width": 64, "tilesets": [{"firstgid": 1}]}"#),
+            "the map has no layers"
+        );
+    }
+
+    #[test]
+    fn the_background_only_holds_terrain() {
+        let towns = vec![("background", layer(&[((7, 3), 5)]))];
+        assert_eq!(
+            tmx_error(&tmx(64, &towns, TMX_OBJECTS)),
+            "background tile (7, 3) uses sprite 4, place towns and the castle as objects"
+        );
+    }
+
+    #[test]
+    fn towns_and_the_castle_must_be_reachable() {
+        // 町 (5, 5) と城 (10, 10) を水で囲む
+        let mut cells = Vec::new();
+        for &(column, row) in [(5, 5), (10, 10)].iter() {
+            for y in row - 1..=row + 1 {
+                for x in column - 1..=column + 1 {
+                    if (x, y) != (column, row) {
+                        cells.push(((x, y), 4));
+                    }
+                }
+            }
+        }
+        let walled = vec![("background", layer(&cells))];
+        let expected =
+            "'town' at (5, 5), 'castle' at (10, 10) can't be reached from the spawn point";
+        assert_eq!(tmx_error(&tmx(64, &walled, TMX_OBJECTS)), expected);
+        assert_eq!(tmj_error(&tmj(&walled, tmj_objects())), expected);
+    }
+
+    #[test]
+    fn objects_must_have_a_known_type() {
+        let objects = format!(
+            r#"{}<object name="dragon" type="monster" x="0" y="0"/>"#,
+            TMX_OBJECTS
+        );
+        assert_eq!(
+            tmx_error(&tmx(64, &layers(), &objects)),
+            "object 'dragon' has an unknown type 'monster', expected town, castle, spawn or encounter"
+        );
+        let mut objects = tmj_objects();
+        objects[0]["properties"][0]["value"] = json!("Shovel");
+        assert_eq!(
+            tmj_error(&tmj(&layers(), objects)),
+            "town 'town' at (5, 5): unknown item 'Shovel'"
+        );
+        let mut objects = tmj_objects();
+        objects[1]["type"] = json!("town");
+        assert_eq!(
+            tmj_error(&tmj(&layers(), objects)),
+            "town 'castle' at (10, 10) has no item"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use rand::Rng;
+
+// Anyone taking a turn in battle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Combatant {
+    // order of the party member
+    Party(usize),
+    // slot of the enemy
+    Enemy(usize),
+}
+
+// Order of the actors in one round. Faster combatants tend to act first,
+// every speed is scaled by 80% to 120% so close speeds can swap places.
+// Ties keep the order of `combatants`.
+pub fn turn_order<R: Rng>(combatants: &[(Combatant, i32)], rng: &mut R) -> Vec<Combatant> {
+    let mut rolls: Vec<(Combatant, i32)> = combatants
+        .iter()
+        .map(|&(combatant, speed)| (combatant, speed.max(0) * rng.gen_range(80..=120)))
+        .collect();
+    rolls.sort_by(|a, b| b.1.cmp(&a.1));
+    rolls.into_iter().map(|(combatant, _)| combatant).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn everyone_acts_once() {
+        let combatants = [
+            (Combatant::Party(0), 10),
+            (Combatant::Party(1), 12),
+            (Combatant::Enemy(0), 8),
+            (Combatant::Enemy(1), 30),
+            (Combatant::Enemy(2), 0),
+        ];
+        let mut rng = StdRng::seed_from_u64(1);
+        let order = turn_order(&combatants, &mut rng);
+        assert_eq!(order.len(), combatants.len());
+        for (combatant, _) in combatants.iter() {
+            assert_eq!(order.iter().filter(|c| *c == combatant).count(), 1);
+        }
+    }
+
+    #[test]
+    fn much_faster_acts_first() {
+        let combatants = [(Combatant::Party(0), 10), (Combatant::Enemy(0), 25)];
+        for seed in 0..100 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            assert_eq!(turn_order(&combatants, &mut rng)[0], Combatant::Enemy(0));
+        }
+    }
+
+    #[test]
+    fn close_speeds_can_swap() {
+        let combatants = [(Combatant::Party(0), 10), (Combatant::Enemy(0), 11)];
+        let firsts: Vec<Combatant> = (0..100)
+            .map(|seed| turn_order(&combatants, &mut StdRng::seed_from_u64(seed))[0])
+            .collect();
+        assert!(firsts.contains(&Combatant::Party(0)));
+        assert!(firsts.contains(&Combatant::Enemy(0)));
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        let combatants = [
+            (Combatant::Party(0), 10),
+            (Combatant::Party(1), 10),
+            (Combatant::Enemy(0), 10),
+        ];
+        let first = turn_order(&combatants, &mut StdRng::seed_from_u64(7));
+        let again = turn_order(&combatants, &mut StdRng::seed_from_u64(7));
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn ties_keep_the_given_order() {
+        let combatants = [(Combatant::Party(0), 0), (Combatant::Enemy(0), 0)];
+        let mut rng = StdRng::seed_from_u64(3);
+        assert_eq!(
+            turn_order(&combatants, &mut rng),
+            vec![Combatant::Party(0), Combatant::Enemy(0)]
+        );
+    }
+
+    #[test]
+    fn nobody_no_turns() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(turn_order(&[], &mut rng).is_empty());
+    }
+}
@@ -1,14 +1,19 @@
 use bevy::prelude::*;
 
 use crate::audio::{AudioEvent, AudioKind};
-use crate::character_status::CharacterStatus;
-use crate::enemies::{level, EnemyData};
+use crate::character_status::{CharacterStatus, Skill, SkillTarget};
+use crate::enemies::{group_text, Battle, EnemySlot};
+use crate::equipment::{candidates, deltas, preview, EquipMenu, SLOTS};
 use crate::events::{GameEvent, RunState};
-use crate::inventory::Inventory;
+use crate::explore_actions::{field_items, FieldMenu};
+use crate::growth::Growth;
+use crate::inventory::{Inventory, Item};
 use crate::loading::FontAssets;
-use crate::map::{Map, Position};
-use crate::player::Player;
-use crate::setup::{ForState, MapCamera};
+use crate::map::Map;
+use crate::party::{PartyMember, RecruitData};
+use crate::player::{Player, PlayerBattleState};
+use crate::setup::ForState;
+use crate::shop::{ShopData, TownMenu, TownScreen, INN_PRICE, TOWN_MENU};
 use crate::AppState;
 
 pub struct UiPlugin;
@@ -18,10 +23,13 @@ impl Plugin for UiPlugin {
         app.add_system_set(
             SystemSet::on_enter(AppState::InGameExplore)
                 .with_system(setup_status_ui.system())
-                .with_system(setup_explore_inventory_ui.system()),
+                .with_system(setup_explore_inventory_ui.system())
+                .with_system(setup_seed_ui.system()),
         )
         .add_system_set(
-            SystemSet::on_update(AppState::InGameExplore).with_system(update_status_ui.system()),
+            SystemSet::on_update(AppState::InGameExplore)
+                .with_system(update_status_ui.system())
+                .with_system(update_explore_inventory_ui.system()),
         )
         .add_system_set(
             SystemSet::on_enter(AppState::InGameBattle)
@@ -33,10 +41,14 @@ impl Plugin for UiPlugin {
             SystemSet::on_update(AppState::InGameBattle)
                 .with_system(update_status_ui.system())
                 .with_system(update_battle_inventory_ui.system())
-                .with_system(update_enemy_status_ui.system()),
+                .with_system(update_enemy_status_ui.system())
+                .with_system(update_dialogue_ui.system()),
         )
         .add_system_set(
             SystemSet::on_enter(AppState::InGameEvent).with_system(setup_event_ui.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGameEvent).with_system(update_town_ui.system()),
         );
     }
 }
@@ -45,18 +57,17 @@ pub struct UiStatusPlayerText;
 pub struct UiStatusEnemyText;
 pub struct UiStatusInventoryText;
 pub struct UiEventText;
+pub struct UiDialogue;
 
 fn setup_status_ui(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    player_query: Query<&CharacterStatus, With<Player>>,
 ) {
-    let player_status = player_query.single().unwrap();
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Percent(25.), Val::Percent(25.)),
+                size: Size::new(Val::Percent(25.), Val::Percent(30.)),
                 position_type: PositionType::Absolute,
                 position: Rect {
                     left: Val::Percent(2.),
@@ -108,8 +119,9 @@ fn setup_status_ui(
                                         margin: Rect::all(Val::Px(5.)),
                                         ..Default::default()
                                     },
+                                    // filled by update_status_ui
                                     text: Text::with_section(
-                                        format!("{}", player_status),
+                                        String::new(),
                                         TextStyle {
                                             font: font_assets.pixel_mplus.clone(),
                                             font_size: 30.0,
@@ -129,12 +141,56 @@ fn setup_status_ui(
 }
 
 fn update_status_ui(
-    query: Query<&CharacterStatus, (With<Player>, Changed<CharacterStatus>)>,
+    state: Res<State<AppState>>,
+    battle: Res<Battle>,
+    player_query: Query<&Player>,
+    party_query: Query<(&CharacterStatus, &Inventory, &PartyMember)>,
     mut status_query: Query<&mut Text, With<UiStatusPlayerText>>,
 ) {
-    for player_status in query.iter() {
-        for mut text in status_query.iter_mut() {
-            text.sections[0].value = format!("{}", player_status);
+    let mut party: Vec<(&CharacterStatus, &Inventory, &PartyMember)> = party_query.iter().collect();
+    party.sort_unstable_by_key(|(_, _, member)| member.order);
+    // 一人旅では詳しいステータスを表示する
+    let (status_text, font_size) = if party.len() == 1 {
+        (format!("{}", party[0].0), 30.)
+    } else {
+        let in_battle = matches!(state.current(), AppState::InGameBattle);
+        let stash = party
+            .first()
+            .map_or(&[][..], |(_, inventory, _)| &inventory.consumables[..]);
+        let choosing_ally = player_query
+            .iter()
+            .any(|player| matches!(player.battle_state, PlayerBattleState::Target))
+            && party.iter().any(|(_, inventory, member)| {
+                member.order == battle.actor && inventory.skill(stash).target() == SkillTarget::Ally
+            });
+        let text = party
+            .iter()
+            .map(|(status, _, member)| {
+                // * は行動中の仲間、> は回復魔法の対象
+                let cursor = if choosing_ally && member.order == battle.ally_target {
+                    "> "
+                } else if in_battle && member.order == battle.actor {
+                    "* "
+                } else {
+                    "  "
+                };
+                format!("{}{}", cursor, status.party_text())
+            })
+            .collect::<String>();
+        (text, 20.)
+    };
+    // お金は主人公が持っている
+    let status_text = match party.first() {
+        Some((_, inventory, _)) if party.len() == 1 => {
+            format!("{}\nGold {} G", status_text, inventory.gold)
+        }
+        Some((_, inventory, _)) => format!("{}Gold {} G", status_text, inventory.gold),
+        None => status_text,
+    };
+    for mut text in status_query.iter_mut() {
+        if text.sections[0].value != status_text {
+            text.sections[0].value = status_text.clone();
+            text.sections[0].style.font_size = font_size;
         }
     }
 }
@@ -149,7 +205,7 @@ fn setup_explore_inventory_ui(
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Percent(25.), Val::Percent(65.)),
+                size: Size::new(Val::Percent(25.), Val::Percent(60.)),
                 position_type: PositionType::Absolute,
                 position: Rect {
                     left: Val::Percent(2.),
@@ -236,17 +292,175 @@ fn setup_explore_inventory_ui(
     audio_event_writer.send(AudioEvent::Play(AudioKind::BGMExplore));
 }
 
+// Items with their counts, or the "Use item" and "Equip" menus while they are open
+fn update_explore_inventory_ui(
+    field_menu: Res<FieldMenu>,
+    equip_menu: Res<EquipMenu>,
+    growth: Res<Growth>,
+    party_query: Query<(&CharacterStatus, &Inventory, &PartyMember)>,
+    mut queries: Query<&mut Text, With<UiStatusInventoryText>>,
+) {
+    let mut party: Vec<(&CharacterStatus, &Inventory, &PartyMember)> = party_query.iter().collect();
+    party.sort_unstable_by_key(|(_, _, member)| member.order);
+    let inventory = match party.first() {
+        Some((_, inventory, _)) => inventory,
+        None => return,
+    };
+    let inventory_text = if equip_menu.open {
+        equip_text(&equip_menu, &growth, &party)
+    } else if field_menu.open {
+        let cursor = |selected: bool| if selected { ">" } else { " " };
+        let items = field_items(inventory);
+        let mut lines = vec!["Use item".to_string()];
+        for (i, item) in items.iter().enumerate() {
+            lines.push(format!(
+                "{} {} x{}",
+                cursor(field_menu.using.is_none() && i == field_menu.cursor),
+                item,
+                inventory.count(item)
+            ));
+        }
+        lines.push(format!(
+            "{} Close",
+            cursor(field_menu.using.is_none() && field_menu.cursor == items.len())
+        ));
+        // 誰に使うか
+        if let Some(item) = field_menu.using {
+            lines.push(format!("{} on", item));
+            for (status, _, member) in party.iter() {
+                lines.push(format!(
+                    "{} {}",
+                    cursor(member.order == field_menu.target),
+                    status.name
+                ));
+            }
+        }
+        lines.push(field_menu.message.clone());
+        lines.join("\n")
+    } else {
+        format!("{}\nEnter: use items\nE: equip", inventory)
+    };
+    for mut text in queries.iter_mut() {
+        if text.sections[0].value != inventory_text {
+            text.sections[0].value = inventory_text.clone();
+        }
+    }
+}
+
+// Gear of one hero, or the pieces for a slot with what they would change
+fn equip_text(
+    menu: &EquipMenu,
+    growth: &Growth,
+    party: &[(&CharacterStatus, &Inventory, &PartyMember)],
+) -> String {
+    let (status, inventory) = match party
+        .iter()
+        .find(|(_, _, member)| member.order == menu.member)
+    {
+        Some((status, inventory, _)) => (*status, *inventory),
+        None => return String::new(),
+    };
+    let cursor = |selected: bool| if selected { ">" } else { " " };
+    let name = |item: Option<Item>| item.map_or("-".to_string(), |item| format!("{}", item));
+    let mut lines = if party.len() > 1 {
+        vec![format!("Equip < {} >", status.name)]
+    } else {
+        vec!["Equip".to_string()]
+    };
+    match menu.choosing {
+        None => {
+            for (i, slot) in SLOTS.iter().enumerate() {
+                lines.push(format!(
+                    "{} {:<9} {}",
+                    cursor(i == menu.cursor),
+                    slot,
+                    name(inventory.equipment.get(*slot))
+                ));
+            }
+            lines.push(format!("{} Close", cursor(menu.cursor == SLOTS.len())));
+        }
+        Some(slot) => {
+            lines.push(format!("{}", slot));
+            let items = candidates(inventory, slot);
+            for (i, item) in items.iter().enumerate() {
+                let worn = if inventory.equipment.is_equipped(item) {
+                    "E"
+                } else {
+                    " "
+                };
+                lines.push(format!("{}{} {}", cursor(i == menu.pick), worn, item));
+            }
+            lines.push(format!("{}  Remove", cursor(menu.pick == items.len())));
+            // 装備する前に能力値の変化を見せる
+            let after = preview(
+                status,
+                growth,
+                &inventory.equipment,
+                slot,
+                items.get(menu.pick).copied(),
+            );
+            for (stat, now, then) in deltas(status, &after) {
+                lines.push(format!("{} {:>3} -> {:>3}", stat, now, then));
+            }
+        }
+    }
+    lines.push(menu.message.clone());
+    lines.join("\n")
+}
+
+fn setup_seed_ui(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    map: Res<Map>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Percent(2.),
+                    bottom: Val::Percent(2.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(5.)),
+                ..Default::default()
+            },
+            material: materials.add(Color::BLACK.into()),
+            ..Default::default()
+        })
+        .insert(ForState {
+            states: vec![AppState::InGameExplore],
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        format!("Seed {} ({})", map.seed, map.source),
+                        TextStyle {
+                            font: font_assets.pixel_mplus.clone(),
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ForState {
+                    states: vec![AppState::InGameExplore],
+                });
+        });
+}
+
 fn setup_battle_inventory_ui(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    inventory_query: Query<&Inventory, With<Player>>,
 ) {
-    let inventory = inventory_query.single().unwrap();
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Percent(25.), Val::Percent(60.)),
+                size: Size::new(Val::Percent(25.), Val::Percent(55.)),
                 position_type: PositionType::Absolute,
                 position: Rect {
                     left: Val::Percent(2.),
@@ -298,8 +512,9 @@ fn setup_battle_inventory_ui(
                                         margin: Rect::all(Val::Px(5.)),
                                         ..Default::default()
                                     },
+                                    // filled by update_battle_inventory_ui
                                     text: Text::with_section(
-                                        format!("{}", inventory.skill_list()),
+                                        String::new(),
                                         TextStyle {
                                             font: font_assets.pixel_mplus.clone(),
                                             font_size: 30.0,
@@ -325,18 +540,7 @@ fn setup_enemy_status_ui(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    map: Res<Map>,
-    enemy_data: Res<EnemyData>,
-    player_query: Query<&CharacterStatus, With<Player>>,
-    player_camera_query: Query<(&MapCamera, &Transform, &Position)>,
 ) {
-    let (_camera, _player_transform, position) = player_camera_query.single().unwrap();
-    let field = map.position_to_field(position);
-    let player_status = player_query.single().unwrap();
-    let enemy_status = enemy_data.create(
-        &field,
-        level(player_status.lv, enemy_data.field_to_enemy(&field)),
-    );
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -388,8 +592,9 @@ fn setup_enemy_status_ui(
                                 margin: Rect::all(Val::Px(5.)),
                                 ..Default::default()
                             },
+                            // filled by update_enemy_status_ui once the enemies are spawned
                             text: Text::with_section(
-                                enemy_status.enemy_text(),
+                                String::new(),
                                 TextStyle {
                                     font: font_assets.pixel_mplus.clone(),
                                     font_size: 30.0,
@@ -411,46 +616,107 @@ fn setup_enemy_status_ui(
 }
 
 fn update_enemy_status_ui(
-    query: Query<&CharacterStatus, (Without<Player>, Changed<CharacterStatus>)>,
+    battle: Res<Battle>,
+    player_query: Query<&Player>,
+    party_query: Query<(&Inventory, &PartyMember)>,
+    query: Query<(&CharacterStatus, &EnemySlot)>,
     mut status_query: Query<&mut Text, With<UiStatusEnemyText>>,
 ) {
-    for enemy_status in query.iter() {
-        for mut text in status_query.iter_mut() {
-            text.sections[0].value = enemy_status.enemy_text();
+    let stash = party_query
+        .iter()
+        .find(|(_, member)| member.order == 0)
+        .map_or(Vec::new(), |(inventory, _)| inventory.consumables.clone());
+    let choosing = player_query
+        .iter()
+        .any(|player| matches!(player.battle_state, PlayerBattleState::Target))
+        && party_query.iter().any(|(inventory, member)| {
+            member.order == battle.actor && inventory.skill(&stash).target() == SkillTarget::Enemy
+        });
+    let mut enemies: Vec<(&CharacterStatus, &EnemySlot)> = query
+        .iter()
+        .filter(|(enemy_status, _)| enemy_status.hp_current > 0)
+        .collect();
+    enemies.sort_unstable_by_key(|(_, slot)| slot.0);
+    // 攻撃対象の選択中はカーソルを表示する
+    let enemy_text = enemies
+        .iter()
+        .map(|(enemy_status, slot)| {
+            let cursor = if choosing && slot.0 == battle.target {
+                "> "
+            } else {
+                "  "
+            };
+            format!("{}{}", cursor, enemy_status.enemy_text())
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    for mut text in status_query.iter_mut() {
+        if text.sections[0].value != enemy_text {
+            text.sections[0].value = enemy_text.clone();
         }
     }
 }
 
 fn update_battle_inventory_ui(
-    query: Query<&Inventory, (With<Player>, Changed<Inventory>)>,
+    battle: Res<Battle>,
+    query: Query<(&CharacterStatus, &Inventory, &PartyMember)>,
     mut queries: Query<&mut Text, With<UiStatusInventoryText>>,
 ) {
-    for inventory in query.iter() {
+    let party_size = query.iter().count();
+    let stash = query
+        .iter()
+        .find(|(_, _, member)| member.order == 0)
+        .map_or(Vec::new(), |(_, inventory, _)| {
+            inventory.consumables.clone()
+        });
+    // 行動中の仲間の技を表示する
+    for (status, inventory, member) in query.iter() {
+        if member.order != battle.actor {
+            continue;
+        }
+        let usable =
+            |skill: &Skill| (battle.can_run || *skill != Skill::Run) && status.can_use(skill);
+        let skill_text = if party_size > 1 {
+            format!("{}\n{}", status.name, inventory.skill_list(&stash, usable))
+        } else {
+            inventory.skill_list(&stash, usable)
+        };
         for mut text in queries.iter_mut() {
-            text.sections[0].value = format!("{}", inventory.skill_list());
+            if text.sections[0].value != skill_text {
+                text.sections[0].value = skill_text.clone();
+            }
         }
     }
 }
 
-fn event_text(state: &RunState) -> String {
+fn event_text(state: &RunState, recruit_data: &RecruitData) -> String {
     match &state.event {
         None => panic!("can't convert text from None."),
         Some(event) => match event {
-            GameEvent::EnemyEncountered(enemy) => {
-                format!("Battle!!!\n{0:?} appeared.\n", enemy)
+            GameEvent::EnemyEncountered(group) => {
+                format!("Battle!!!\n{} appeared.\n", group_text(group))
             }
+            // the town menu follows, see town_text
             GameEvent::TownArrived(item, visited) => {
                 if *visited {
-                    format!("Town\nGet healed up your HP!\n")
+                    format!("Town\n")
+                } else if let Some(recruit) = recruit_data.recruit(item) {
+                    format!("Town\n{} joined with a {:?}!\n", recruit.name, item)
                 } else {
-                    format!("Town\nGet healed up your HP!\nGet a {:?}!", item)
+                    format!("Town\nGet a {:?}!\n", item)
                 }
             }
-            GameEvent::Win(levelup) => {
-                if *levelup {
-                    return format!("You Win!\nLevel Up!\n");
+            GameEvent::DungeonEntered => {
+                format!("Castle\nThe Lich waits on the top floor.\n")
+            }
+            GameEvent::FloorChanged(_) => String::new(),
+            GameEvent::Win(levelups, gold) => {
+                let mut text = format!("You Win!\nYou got {} G.\n", gold);
+                // 誰が何をどれだけ伸ばしたか
+                for levelup in levelups {
+                    text.push_str(&format!("{}\n", levelup));
                 }
-                return format!("You Win!\n");
+                return text;
             }
             GameEvent::Lose => {
                 format!("You Lose!\n")
@@ -462,13 +728,165 @@ fn event_text(state: &RunState) -> String {
     }
 }
 
+// The town menu, the shop or the items to sell with the gold of the hero
+fn town_text(
+    town_menu: &TownMenu,
+    shop_data: &ShopData,
+    inventory: &Inventory,
+    party_size: usize,
+) -> String {
+    let cursor = |i: usize| if i == town_menu.cursor { ">" } else { " " };
+    let mut lines = vec![format!("Gold {} G", inventory.gold)];
+    match town_menu.screen {
+        TownScreen::Menu => {
+            for (i, entry) in TOWN_MENU.iter().enumerate() {
+                if i == 0 {
+                    let price = INN_PRICE * party_size as u32;
+                    lines.push(format!("{} {} ({} G)", cursor(i), entry, price));
+                } else {
+                    lines.push(format!("{} {}", cursor(i), entry));
+                }
+            }
+        }
+        TownScreen::Buy => {
+            for (i, item) in shop_data.goods.iter().enumerate() {
+                let owned = if inventory.items.contains(item) {
+                    " (owned)".to_string()
+                } else if inventory.count(item) > 0 {
+                    format!(" (x{})", inventory.count(item))
+                } else {
+                    String::new()
+                };
+                let price = shop_data.price(item).unwrap_or(0);
+                lines.push(format!("{} {} {} G{}", cursor(i), item, price, owned));
+            }
+            let count = shop_data.goods.len();
+            lines.push(format!("{} Sell", cursor(count)));
+            lines.push(format!("{} Back", cursor(count + 1)));
+        }
+        TownScreen::Sell => {
+            let items = shop_data.sellable(inventory);
+            for (i, item) in items.iter().enumerate() {
+                let price = shop_data.sell_price(item).unwrap_or(0);
+                if item.is_consumable() {
+                    let count = inventory.count(item);
+                    lines.push(format!("{} {} x{} {} G", cursor(i), item, count, price));
+                } else {
+                    lines.push(format!("{} {} {} G", cursor(i), item, price));
+                }
+            }
+            lines.push(format!("{} Back", cursor(items.len())));
+        }
+    }
+    lines.push(town_menu.message.clone());
+    lines.push("Press 'S' to save.".to_string());
+    lines.join("\n")
+}
+
 fn setup_event_ui(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     runstate: Res<RunState>,
+    recruit_data: Res<RecruitData>,
+    town_menu: Res<TownMenu>,
+    shop_data: Res<ShopData>,
+    party_query: Query<(&Inventory, &PartyMember)>,
     mut audio_event_writer: EventWriter<AudioEvent>,
 ) {
+    // 町のメニューは長いので小さな文字で表示する
+    let (text, font_size) = match town_event_text(
+        &runstate,
+        &recruit_data,
+        &town_menu,
+        &shop_data,
+        &party_query,
+    ) {
+        Some(text) => (text, 36.),
+        // レベルアップの内訳も同じ大きさで
+        None => match &runstate.event {
+            Some(GameEvent::Win(levelups, _)) if !levelups.is_empty() => {
+                (event_text(&*runstate, &*recruit_data), 36.)
+            }
+            _ => (event_text(&*runstate, &*recruit_data), 90.),
+        },
+    };
+    spawn_event_window(
+        &mut commands,
+        &font_assets,
+        &mut materials,
+        text,
+        font_size,
+        AppState::InGameEvent,
+    );
+
+    match runstate.event.as_ref().unwrap() {
+        GameEvent::TownArrived(_, _) => {
+            audio_event_writer.send(AudioEvent::Play(AudioKind::SETown));
+        }
+        GameEvent::Win(..) | GameEvent::WinLast => {
+            audio_event_writer.send(AudioEvent::Play(AudioKind::BGMWin));
+        }
+        GameEvent::Lose => {
+            audio_event_writer.send(AudioEvent::Play(AudioKind::BGMLose));
+        }
+        _ => {}
+    }
+}
+
+fn town_event_text(
+    runstate: &RunState,
+    recruit_data: &RecruitData,
+    town_menu: &TownMenu,
+    shop_data: &ShopData,
+    party_query: &Query<(&Inventory, &PartyMember)>,
+) -> Option<String> {
+    if !matches!(runstate.event, Some(GameEvent::TownArrived(_, _))) {
+        return None;
+    }
+    let party_size = party_query.iter().count();
+    let (inventory, _) = party_query.iter().find(|(_, member)| member.order == 0)?;
+    Some(format!(
+        "{}{}",
+        event_text(runstate, recruit_data),
+        town_text(town_menu, shop_data, inventory, party_size)
+    ))
+}
+
+fn update_town_ui(
+    runstate: Res<RunState>,
+    recruit_data: Res<RecruitData>,
+    town_menu: Res<TownMenu>,
+    shop_data: Res<ShopData>,
+    party_query: Query<(&Inventory, &PartyMember)>,
+    mut text_query: Query<&mut Text, With<UiEventText>>,
+) {
+    let text = match town_event_text(
+        &runstate,
+        &recruit_data,
+        &town_menu,
+        &shop_data,
+        &party_query,
+    ) {
+        Some(text) => text,
+        None => return,
+    };
+    for mut event_text in text_query.iter_mut() {
+        if event_text.sections[0].value != text {
+            event_text.sections[0].value = text.clone();
+        }
+    }
+}
+
+// The black window of events, also used for what a boss says in battle
+fn spawn_event_window(
+    commands: &mut Commands,
+    font_assets: &FontAssets,
+    materials: &mut Assets<ColorMaterial>,
+    text: String,
+    font_size: f32,
+    state: AppState,
+) -> Entity {
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
@@ -485,7 +903,7 @@ fn setup_event_ui(
             ..Default::default()
         })
         .insert(ForState {
-            states: vec![AppState::InGameEvent],
+            states: vec![state.clone()],
         })
         .with_children(|parent| {
             parent
@@ -501,7 +919,7 @@ fn setup_event_ui(
                     ..Default::default()
                 })
                 .insert(ForState {
-                    states: vec![AppState::InGameEvent],
+                    states: vec![state.clone()],
                 })
                 .with_children(|parent| {
                     parent
@@ -511,10 +929,10 @@ fn setup_event_ui(
                                 ..Default::default()
                             },
                             text: Text::with_section(
-                                event_text(&*runstate),
+                                text,
                                 TextStyle {
                                     font: font_assets.pixel_mplus.clone(),
-                                    font_size: 90.0,
+                                    font_size,
                                     color: Color::WHITE,
                                 },
                                 TextAlignment {
@@ -525,23 +943,47 @@ fn setup_event_ui(
                             ..Default::default()
                         })
                         .insert(ForState {
-                            states: vec![AppState::InGameEvent],
+                            states: vec![state.clone()],
                         })
                         .insert(UiEventText);
                 });
         })
-        .id();
+        .id()
+}
 
-    match runstate.event.as_ref().unwrap() {
-        GameEvent::TownArrived(_, _) => {
-            audio_event_writer.send(AudioEvent::Play(AudioKind::SETown));
-        }
-        GameEvent::Win(_) | GameEvent::WinLast => {
-            audio_event_writer.send(AudioEvent::Play(AudioKind::BGMWin));
+// Shows the first unread line of a boss over the battle
+fn update_dialogue_ui(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    battle: Res<Battle>,
+    window_query: Query<Entity, With<UiDialogue>>,
+    mut text_query: Query<&mut Text, With<UiEventText>>,
+) {
+    let line = match battle.dialogue.front() {
+        Some(line) => line,
+        None => {
+            for entity in window_query.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+            return;
         }
-        GameEvent::Lose => {
-            audio_event_writer.send(AudioEvent::Play(AudioKind::BGMLose));
+    };
+    if window_query.iter().next().is_none() {
+        let entity = spawn_event_window(
+            &mut commands,
+            &font_assets,
+            &mut materials,
+            line.clone(),
+            90.,
+            AppState::InGameBattle,
+        );
+        commands.entity(entity).insert(UiDialogue);
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != *line {
+            text.sections[0].value = line.clone();
         }
-        _ => {}
     }
 }
@@ -0,0 +1,489 @@
+use crate::inventory::Item;
+use crate::map::{unreachable_fields, EncounterZone, Field, MAP_SIZE};
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use core::fmt;
+use std::collections::HashMap;
+use std::error::Error;
+
+// Hand-authored world map stored as plain text in `assets/maps/*.map`.
+//
+//   [grid]      MAP_SIZE[1] rows of MAP_SIZE[0] characters, top row first
+//               '.' Grass, '*' Forest, '^' Mountain, '~' Water
+//   [towns]     `column, row = Item` for every town, e.g. `12, 5 = SpellFire(1)`
+//   [castle]    `column, row` of the Lich castle
+//
+// Columns and rows are 0-based grid coordinates as seen in the file.
+// Every town and the castle must be reachable from the start in the middle of the grid.
+// Empty lines and lines starting with '#' are ignored.
+#[derive(Clone, Debug, Default, TypeUuid)]
+#[uuid = "5b7d6a0e-3f43-4c1e-9a55-8e0d3c2f61a4"]
+pub struct WorldMapAsset {
+    pub fields: HashMap<(i32, i32), Field>,
+    pub start: (i32, i32),
+    // sprite indices per layer, the background falls back to the field sprite
+    pub background: HashMap<(i32, i32), usize>,
+    pub foreground: HashMap<(i32, i32), usize>,
+    pub encounter_zones: HashMap<(i32, i32), EncounterZone>,
+}
+
+#[derive(Default)]
+pub struct WorldMapLoader;
+
+impl AssetLoader for WorldMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let text = std::str::from_utf8(bytes)?;
+            let world_map = parse_world_map(text)?;
+            load_context.set_default_asset(LoadedAsset::new(world_map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map"]
+    }
+}
+
+#[derive(Debug)]
+pub struct MapParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+impl fmt::Display for MapParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+impl Error for MapParseError {}
+
+fn parse_error(line: usize, column: usize, message: String) -> MapParseError {
+    MapParseError {
+        line,
+        column,
+        message,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    None,
+    Grid,
+    Towns,
+    Castle,
+}
+
+pub fn parse_world_map(text: &str) -> Result<WorldMapAsset, MapParseError> {
+    let width = MAP_SIZE[0] as usize;
+    let height = MAP_SIZE[1] as usize;
+    let mut section = Section::None;
+    let mut rows: Vec<(usize, Vec<Field>)> = Vec::with_capacity(height);
+    let mut towns: Vec<(usize, usize, (usize, usize), Item)> = Vec::new();
+    let mut castle: Option<(usize, usize, (usize, usize))> = None;
+    let mut last_line = 0;
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        last_line = line;
+        let content = raw.trim_end();
+        if content.trim().is_empty() || content.trim_start().starts_with('#') {
+            continue;
+        }
+        match content.trim() {
+            "[grid]" => {
+                section = Section::Grid;
+                continue;
+            }
+            "[towns]" => {
+                section = Section::Towns;
+                continue;
+            }
+            "[castle]" => {
+                section = Section::Castle;
+                continue;
+            }
+            header if header.starts_with('[') => {
+                return Err(parse_error(
+                    line,
+                    column_at(raw, trim_at(content, 0).1),
+                    format!("unknown section '{}'", header),
+                ));
+            }
+            _ => {}
+        }
+
+        match section {
+            Section::None => {
+                return Err(parse_error(
+                    line,
+                    1,
+                    "expected a section header like '[grid]'".to_string(),
+                ));
+            }
+            Section::Grid => {
+                if rows.len() == height {
+                    return Err(parse_error(
+                        line,
+                        1,
+                        format!("grid has more than {} rows", height),
+                    ));
+                }
+                let mut row = Vec::with_capacity(width);
+                for (column, c) in content.chars().enumerate() {
+                    let field = match c {
+                        '.' => Field::Grass,
+                        '*' => Field::Forest,
+                        '^' => Field::Mountain,
+                        '~' => Field::Water,
+                        _ => {
+                            return Err(parse_error(
+                                line,
+                                column + 1,
+                                format!("unknown terrain '{}', expected one of . * ^ ~", c),
+                            ))
+                        }
+                    };
+                    row.push(field);
+                }
+                if row.len() != width {
+                    return Err(parse_error(
+                        line,
+                        row.len().min(width) + 1,
+                        format!("grid row has {} columns, expected {}", row.len(), width),
+                    ));
+                }
+                rows.push((line, row));
+            }
+            Section::Towns => {
+                let (cell, eq) = match content.find('=') {
+                    Some(eq) => (&content[..eq], eq),
+                    None => {
+                        return Err(parse_error(
+                            line,
+                            1,
+                            "expected 'column, row = Item'".to_string(),
+                        ))
+                    }
+                };
+                let point = parse_cell(line, raw, cell, 0)?;
+                let cell_column = column_at(raw, trim_at(cell, 0).1);
+                let (item_text, item_offset) = trim_at(&content[eq + 1..], eq + 1);
+                let item = item_text
+                    .parse::<Item>()
+                    .map_err(|message| parse_error(line, column_at(raw, item_offset), message))?;
+                if let Some((town_line, _, _, _)) = towns.iter().find(|town| town.2 == point) {
+                    return Err(parse_error(
+                        line,
+                        cell_column,
+                        format!(
+                            "a town at {:?} is already defined on line {}",
+                            point, town_line
+                        ),
+                    ));
+                }
+                towns.push((line, cell_column, point, item));
+            }
+            Section::Castle => {
+                if let Some((castle_line, _, _)) = castle {
+                    return Err(parse_error(
+                        line,
+                        1,
+                        format!("the castle is already defined on line {}", castle_line),
+                    ));
+                }
+                castle = Some((
+                    line,
+                    column_at(raw, trim_at(content, 0).1),
+                    parse_cell(line, raw, content, 0)?,
+                ));
+            }
+        }
+    }
+
+    if rows.len() != height {
+        return Err(parse_error(
+            last_line.max(1),
+            1,
+            format!("grid has {} rows, expected {}", rows.len(), height),
+        ));
+    }
+
+    let mut fields = HashMap::with_capacity(width * height);
+    for (row, (_line, cells)) in rows.iter().enumerate() {
+        for (column, field) in cells.iter().enumerate() {
+            fields.insert(to_position((column, row)), field.clone());
+        }
+    }
+
+    let (start_line, start_row) = &rows[height - 1 - height / 2];
+    if start_row[width / 2].is_obstacle() {
+        return Err(parse_error(
+            *start_line,
+            width / 2 + 1,
+            "the start position must not be an obstacle".to_string(),
+        ));
+    }
+
+    for (line, column, point, item) in towns.iter() {
+        if point == &(width / 2, height - 1 - height / 2) {
+            return Err(parse_error(
+                *line,
+                *column,
+                "a town cannot be placed on the start position".to_string(),
+            ));
+        }
+        fields.insert(
+            to_position(*point),
+            Field::Town {
+                item: *item,
+                visited: false,
+            },
+        );
+    }
+
+    match castle {
+        Some((line, column, point)) => {
+            if towns.iter().any(|town| town.2 == point) {
+                return Err(parse_error(
+                    line,
+                    column,
+                    format!("the castle overlaps a town at {:?}", point),
+                ));
+            }
+            fields.insert(to_position(point), Field::Castle);
+        }
+        None => {
+            return Err(parse_error(
+                last_line.max(1),
+                1,
+                "missing [castle] section".to_string(),
+            ))
+        }
+    }
+
+    // 町と城はスタート地点から歩いて行けること
+    if let Some(position) = unreachable_fields(&fields, (0, 0)).first() {
+        let (line, column, point) = towns
+            .iter()
+            .map(|(line, column, point, _item)| (*line, *column, *point))
+            .chain(castle)
+            .find(|(_line, _column, point)| to_position(*point) == *position)
+            .expect("only towns and the castle can be unreachable");
+        return Err(parse_error(
+            line,
+            column,
+            format!("{:?} can't be reached from the start position", point),
+        ));
+    }
+
+    Ok(WorldMapAsset {
+        fields,
+        ..Default::default()
+    })
+}
+
+// Grid column and row (top row first) to a map position
+pub fn to_position((column, row): (usize, usize)) -> (i32, i32) {
+    let width = MAP_SIZE[0] as i32;
+    let height = MAP_SIZE[1] as i32;
+    (
+        column as i32 - width / 2,
+        height - 1 - row as i32 - height / 2,
+    )
+}
+
+// `column, row` inside the grid, `text` starts at byte `offset` of the line
+fn parse_cell(
+    line: usize,
+    raw: &str,
+    text: &str,
+    offset: usize,
+) -> Result<(usize, usize), MapParseError> {
+    let width = MAP_SIZE[0] as usize;
+    let height = MAP_SIZE[1] as usize;
+    let mut numbers = Vec::new();
+    let mut start = offset;
+    for number in text.split(',') {
+        numbers.push(trim_at(number, start));
+        start += number.len() + 1;
+    }
+    match numbers[..] {
+        [(column, column_offset), (row, row_offset)] => {
+            let column_value = column.parse::<usize>().map_err(|_| {
+                parse_error(
+                    line,
+                    column_at(raw, column_offset),
+                    format!("invalid column '{}'", column),
+                )
+            })?;
+            let row_value = row.parse::<usize>().map_err(|_| {
+                parse_error(
+                    line,
+                    column_at(raw, row_offset),
+                    format!("invalid row '{}'", row),
+                )
+            })?;
+            if column_value >= width {
+                return Err(parse_error(
+                    line,
+                    column_at(raw, column_offset),
+                    format!(
+                        "column {} is outside the grid (0 to {})",
+                        column_value,
+                        width - 1
+                    ),
+                ));
+            }
+            if row_value >= height {
+                return Err(parse_error(
+                    line,
+                    column_at(raw, row_offset),
+                    format!(
+                        "row {} is outside the grid (0 to {})",
+                        row_value,
+                        height - 1
+                    ),
+                ));
+            }
+            Ok((column_value, row_value))
+        }
+        _ => {
+            let (text, offset) = trim_at(text, offset);
+            Err(parse_error(
+                line,
+                column_at(raw, offset),
+                format!("expected 'column, row' but found '{}'", text),
+            ))
+        }
+    }
+}
+
+// `part` without surrounding spaces, and the byte offset it starts at
+// when `part` itself starts at `offset`
+fn trim_at(part: &str, offset: usize) -> (&str, usize) {
+    let trimmed = part.trim_start();
+    (trimmed.trim_end(), offset + part.len() - trimmed.len())
+}
+
+// 1-based column of a byte offset in the line
+fn column_at(raw: &str, offset: usize) -> usize {
+    raw[..offset].chars().count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Grass everywhere with `rows` replacing the top rows of the grid
+    fn map_text(rows: &[&str], towns: &str, castle: &str) -> String {
+        let width = MAP_SIZE[0] as usize;
+        let height = MAP_SIZE[1] as usize;
+        let mut text = String::from("# test map\n[grid]\n");
+        for row in 0..height {
+            match rows.get(row) {
+                Some(line) => text.push_str(line),
+                None => text.push_str(&".".repeat(width)),
+            }
+            text.push('\n');
+        }
+        format!("{}[towns]\n{}\n[castle]\n{}\n", text, towns, castle)
+    }
+
+    fn error(text: &str) -> (usize, usize, String) {
+        let err = parse_world_map(text).unwrap_err();
+        (err.line, err.column, err.message)
+    }
+
+    #[test]
+    fn a_valid_map_is_loaded() {
+        let forest = format!("*{}", ".".repeat(63));
+        let text = map_text(
+            &[&forest],
+            "3, 4 = SpellFire(1)\n60, 40 = HeroSword",
+            "10, 20",
+        );
+        let world_map = parse_world_map(&text).unwrap();
+        assert_eq!(world_map.fields.len(), (MAP_SIZE[0] * MAP_SIZE[1]) as usize);
+        assert_eq!(world_map.fields[&to_position((0, 0))], Field::Forest);
+        assert_eq!(world_map.fields[&to_position((1, 0))], Field::Grass);
+        assert_eq!(
+            world_map.fields[&to_position((3, 4))],
+            Field::Town {
+                item: Item::SpellFire(1),
+                visited: false
+            }
+        );
+        assert_eq!(world_map.fields[&to_position((10, 20))], Field::Castle);
+        assert_eq!(world_map.start, (0, 0));
+    }
+
+    #[test]
+    fn an_unknown_glyph_names_its_line_and_column() {
+        let row = format!("{}X{}", ".".repeat(9), ".".repeat(54));
+        let text = map_text(&["", "", &row], "", "10, 20");
+        // 3 行目の行は "# test map" と "[grid]" の後
+        assert_eq!(
+            error(&text),
+            (
+                5,
+                10,
+                "unknown terrain 'X', expected one of . * ^ ~".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn ragged_rows_are_rejected() {
+        let short = ".".repeat(63);
+        let long = ".".repeat(65);
+        assert_eq!(
+            error(&map_text(&[&short], "", "10, 20")),
+            (3, 64, "grid row has 63 columns, expected 64".to_string())
+        );
+        assert_eq!(
+            error(&map_text(&[&long], "", "10, 20")).2,
+            "grid row has 65 columns, expected 64"
+        );
+    }
+
+    #[test]
+    fn columns_count_from_the_start_of_the_line() {
+        let text = map_text(&[], "  70, 4 = Herb", "10, 20");
+        assert_eq!(
+            error(&text),
+            (52, 3, "column 70 is outside the grid (0 to 63)".to_string())
+        );
+        let text = map_text(&[], "3, 4 =  Shovel", "10, 20");
+        assert_eq!(error(&text), (52, 9, "unknown item 'Shovel'".to_string()));
+        let text = map_text(&[], "3, 4 = Herb", "10,  99");
+        assert_eq!(
+            error(&text),
+            (54, 6, "row 99 is outside the grid (0 to 47)".to_string())
+        );
+    }
+
+    #[test]
+    fn an_unreachable_town_is_an_error() {
+        let water = "~".repeat(64);
+        let island = format!("~~~.{}", "~".repeat(60));
+        let text = map_text(&[&water, &island, &water], "3, 1 = Herb", "10, 20");
+        assert_eq!(
+            error(&text),
+            (
+                52,
+                1,
+                "(3, 1) can't be reached from the start position".to_string()
+            )
+        );
+    }
+}